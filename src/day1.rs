@@ -1,46 +1,462 @@
-use std::{collections::HashMap, iter::zip};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Write},
+    io::{self, BufRead},
+    iter::zip,
+};
 
-pub fn day1_part1(input: String) -> usize {
-    let (mut list1, mut list2) = parse(input);
-    list1.sort_unstable();
-    list2.sort_unstable();
+use itertools::Itertools;
 
-    let mut total_distance = 0;
+use crate::profile::phase;
 
-    for (value1, value2) in zip(list1, list2) {
-        total_distance += value1.abs_diff(value2)
+#[derive(Debug)]
+pub enum LocationParseError {
+    Io(io::Error),
+    MissingValue { line: usize },
+    UnexpectedValue { line: usize, value: String },
+    InvalidNumber { line: usize, value: String },
+}
+
+impl From<io::Error> for LocationParseError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl Display for LocationParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LocationParseError::Io(e) => write!(f, "Failed to read input: {e}"),
+            LocationParseError::MissingValue { line } => {
+                write!(f, "Line {line}: expected two location IDs")
+            }
+            LocationParseError::UnexpectedValue { line, value } => {
+                write!(f, "Line {line}: unexpected value {value}")
+            }
+            LocationParseError::InvalidNumber { line, value } => {
+                write!(f, "Line {line}: invalid location ID {value}")
+            }
+        }
     }
+}
 
-    total_distance
+/// Occurrence counts of the IDs in both lists. Memory is bounded by the
+/// number of distinct IDs, not by the length of the lists.
+#[derive(Debug, Default)]
+struct LocationCounts {
+    left: BTreeMap<usize, usize>,
+    right: BTreeMap<usize, usize>,
 }
 
-pub fn day1_part2(input: String) -> usize {
-    let (list1, list2) = parse(input);
+impl LocationCounts {
+    fn from_reader(input: impl BufRead) -> Result<Self, LocationParseError> {
+        let mut counts = Self::default();
+        read_pairs(input, |left, right| {
+            *counts.left.entry(left).or_insert(0) += 1;
+            *counts.right.entry(right).or_insert(0) += 1;
+        })?;
+
+        Ok(counts)
+    }
+
+    /// Distance between the sorted lists, walking both count tables in order.
+    fn total_distance(&self) -> usize {
+        let mut left = self.left.iter().map(|(id, count)| (*id, *count));
+        let mut right = self.right.iter().map(|(id, count)| (*id, *count));
+        let mut current = (left.next(), right.next());
+        let mut total_distance = 0;
+
+        while let (Some((left_id, left_count)), Some((right_id, right_count))) = current {
+            let pairs = left_count.min(right_count);
+            total_distance += pairs * left_id.abs_diff(right_id);
 
-    let mut counts = HashMap::<usize, usize>::new();
-    for value in list2 {
-        counts.insert(value, counts.get(&value).unwrap_or(&0) + 1);
+            current = (
+                match left_count - pairs {
+                    0 => left.next(),
+                    remaining => Some((left_id, remaining)),
+                },
+                match right_count - pairs {
+                    0 => right.next(),
+                    remaining => Some((right_id, remaining)),
+                },
+            );
+        }
+
+        total_distance
     }
 
-    let mut score: usize = 0;
-    for value in list1 {
-        score += value * counts.get(&value).unwrap_or(&0);
+    fn similarity_score(&self) -> usize {
+        self.left
+            .iter()
+            .map(|(id, count)| id * count * self.right.get(id).unwrap_or(&0))
+            .sum()
     }
+}
+
+pub fn day1_part1(input: impl BufRead) -> Result<usize, LocationParseError> {
+    let counts = phase("LocationCounts::from_reader", || {
+        LocationCounts::from_reader(input)
+    })?;
+
+    Ok(phase("LocationCounts::total_distance", || {
+        counts.total_distance()
+    }))
+}
+
+pub fn day1_part2(input: impl BufRead) -> Result<usize, LocationParseError> {
+    let counts = phase("LocationCounts::from_reader", || {
+        LocationCounts::from_reader(input)
+    })?;
+
+    Ok(phase("LocationCounts::similarity_score", || {
+        counts.similarity_score()
+    }))
+}
+
+/// Both historians' location lists, for auditing how well they reconcile.
+struct LocationLists {
+    left: Vec<usize>,
+    right: Vec<usize>,
+}
 
-    score
+#[derive(Debug, PartialEq, Eq)]
+struct SimilarityContribution {
+    id: usize,
+    left_count: usize,
+    right_count: usize,
+    score: usize,
 }
 
-fn parse(input: String) -> (Vec<usize>, Vec<usize>) {
+impl LocationLists {
+    fn from_input(input: String) -> Result<Self, LocationParseError> {
+        let (left, right) = parse(input)?;
+        Ok(Self { left, right })
+    }
+
+    fn left_frequencies(&self) -> BTreeMap<usize, usize> {
+        frequencies(&self.left)
+    }
+
+    fn right_frequencies(&self) -> BTreeMap<usize, usize> {
+        frequencies(&self.right)
+    }
+
+    /// IDs of the left list that never appear in the right list.
+    fn missing_from_right(&self) -> BTreeSet<usize> {
+        let right: BTreeSet<usize> = self.right.iter().copied().collect();
+        self.left
+            .iter()
+            .copied()
+            .filter(|id| !right.contains(id))
+            .collect()
+    }
+
+    /// IDs of the right list that never appear in the left list.
+    fn missing_from_left(&self) -> BTreeSet<usize> {
+        let left: BTreeSet<usize> = self.left.iter().copied().collect();
+        self.right
+            .iter()
+            .copied()
+            .filter(|id| !left.contains(id))
+            .collect()
+    }
+
+    /// Distances between the pairs of the sorted lists, in ascending order.
+    fn distances(&self) -> Vec<usize> {
+        let left = self.left.iter().sorted_unstable();
+        let right = self.right.iter().sorted_unstable();
+
+        zip(left, right)
+            .map(|(value1, value2)| value1.abs_diff(*value2))
+            .sorted_unstable()
+            .collect()
+    }
+
+    /// Nearest-rank percentile of the pairwise distances.
+    fn percentile_distance(&self, percentile: f64) -> Option<usize> {
+        let distances = self.distances();
+        if distances.is_empty() {
+            return None;
+        }
+
+        let rank = (percentile / 100.0 * distances.len() as f64).ceil() as usize;
+        Some(distances[rank.clamp(1, distances.len()) - 1])
+    }
+
+    fn median_distance(&self) -> Option<usize> {
+        self.percentile_distance(50.0)
+    }
+
+    /// The `k` IDs contributing most to the similarity score.
+    fn top_similarity_contributors(&self, k: usize) -> Vec<SimilarityContribution> {
+        let right = self.right_frequencies();
+
+        self.left_frequencies()
+            .into_iter()
+            .filter_map(|(id, left_count)| {
+                right.get(&id).map(|right_count| SimilarityContribution {
+                    id,
+                    left_count,
+                    right_count: *right_count,
+                    score: id * left_count * right_count,
+                })
+            })
+            .sorted_unstable_by(|a, b| b.score.cmp(&a.score).then(a.id.cmp(&b.id)))
+            .take(k)
+            .collect()
+    }
+
+    fn report(&self, k: usize) -> String {
+        let distances = self.distances();
+        let similarity: usize = self
+            .top_similarity_contributors(usize::MAX)
+            .iter()
+            .map(|contribution| contribution.score)
+            .sum();
+        let missing_from_right = self.missing_from_right();
+        let missing_from_left = self.missing_from_left();
+
+        let mut output = String::new();
+        writeln!(output, "Pairs: {}", distances.len()).unwrap();
+        writeln!(
+            output,
+            "Total distance: {}",
+            distances.iter().sum::<usize>()
+        )
+        .unwrap();
+        writeln!(output, "Similarity score: {similarity}").unwrap();
+        writeln!(
+            output,
+            "Distance median: {}, p90: {}, p99: {}, max: {}",
+            self.median_distance().unwrap_or(0),
+            self.percentile_distance(90.0).unwrap_or(0),
+            self.percentile_distance(99.0).unwrap_or(0),
+            distances.last().unwrap_or(&0),
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "Missing from right list ({}): {}",
+            missing_from_right.len(),
+            missing_from_right.iter().join(", ")
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "Missing from left list ({}): {}",
+            missing_from_left.len(),
+            missing_from_left.iter().join(", ")
+        )
+        .unwrap();
+        for (side, frequencies) in [
+            ("left", self.left_frequencies()),
+            ("right", self.right_frequencies()),
+        ] {
+            writeln!(
+                output,
+                "Most frequent {side} IDs: {}",
+                frequencies
+                    .into_iter()
+                    .sorted_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)))
+                    .take(k)
+                    .map(|(id, count)| format!("{id} ({count}x)"))
+                    .join(", ")
+            )
+            .unwrap();
+        }
+        writeln!(output, "Top {k} similarity contributors:").unwrap();
+        for contribution in self.top_similarity_contributors(k) {
+            writeln!(
+                output,
+                "  {}: {} ({} left x {} right)",
+                contribution.id,
+                contribution.score,
+                contribution.left_count,
+                contribution.right_count
+            )
+            .unwrap();
+        }
+
+        output
+    }
+}
+
+fn frequencies(list: &[usize]) -> BTreeMap<usize, usize> {
+    let mut counts = BTreeMap::new();
+    for value in list {
+        *counts.entry(*value).or_insert(0) += 1;
+    }
+    counts
+}
+
+pub fn day1_stats(input: String, top: usize) -> Result<String, LocationParseError> {
+    let lists = phase("LocationLists::from_input", || {
+        LocationLists::from_input(input)
+    })?;
+
+    Ok(phase("LocationLists::report", || lists.report(top)))
+}
+
+fn parse(input: String) -> Result<(Vec<usize>, Vec<usize>), LocationParseError> {
     let mut list1 = Vec::<usize>::new();
     let mut list2 = Vec::<usize>::new();
 
-    let lines = input.trim().split("\n");
-    for line in lines {
-        let values = line.split("   ").collect::<Vec<&str>>();
+    read_pairs(input.as_bytes(), |value1, value2| {
+        list1.push(value1);
+        list2.push(value2);
+    })?;
+
+    Ok((list1, list2))
+}
+
+/// Call `f` with the pair of IDs on every non-blank line. Lines are numbered as in the raw
+/// input, so errors point at the same line no matter how the input is read.
+fn read_pairs(
+    mut input: impl BufRead,
+    mut f: impl FnMut(usize, usize),
+) -> Result<(), LocationParseError> {
+    let mut line = String::new();
+    let mut line_number = 0;
+
+    while input.read_line(&mut line)? > 0 {
+        line_number += 1;
+        let content = match line_number {
+            1 => line.trim_start_matches('\u{feff}').trim(),
+            _ => line.trim(),
+        };
+        if !content.is_empty() {
+            let (left, right) = parse_line(line_number, content)?;
+            f(left, right);
+        }
+        line.clear();
+    }
+
+    Ok(())
+}
+
+fn parse_line(line_number: usize, line: &str) -> Result<(usize, usize), LocationParseError> {
+    let mut values = line.split_whitespace().map(|value| {
+        value
+            .parse::<usize>()
+            .map_err(|_| LocationParseError::InvalidNumber {
+                line: line_number,
+                value: value.to_string(),
+            })
+    });
+
+    let missing = || LocationParseError::MissingValue { line: line_number };
+    let left = values.next().ok_or_else(missing)??;
+    let right = values.next().ok_or_else(missing)??;
+
+    if let Some(value) = line.split_whitespace().nth(2) {
+        return Err(LocationParseError::UnexpectedValue {
+            line: line_number,
+            value: value.to_string(),
+        });
+    }
+
+    Ok((left, right))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use crate::day1::{
+        day1_part1, day1_part2, day1_stats, LocationCounts, LocationLists, LocationParseError,
+        SimilarityContribution,
+    };
+
+    const EXAMPLE: &str = r#"3   4
+4   3
+2   5
+1   3
+3   9
+3   3"#;
 
-        list1.push(values[0].parse().unwrap());
-        list2.push(values[1].parse().unwrap());
+    #[test]
+    fn test_part1() {
+        assert_eq!(11, day1_part1(EXAMPLE.as_bytes()).unwrap());
     }
 
-    (list1, list2)
+    #[test]
+    fn test_part2() {
+        assert_eq!(31, day1_part2(EXAMPLE.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_any_whitespace() {
+        let counts = LocationCounts::from_reader(
+            "\u{feff}3 4\r\n4\t3\n\n2    5\n1 3\n  3 9  \n3\t 3\n".as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(11, counts.total_distance());
+        assert_eq!(31, counts.similarity_score());
+    }
+
+    #[test]
+    fn test_errors_name_the_line() {
+        assert!(matches!(
+            LocationCounts::from_reader("3 4\n\n4 x\n".as_bytes()),
+            Err(LocationParseError::InvalidNumber { line: 3, value }) if value == "x"
+        ));
+        assert!(matches!(
+            LocationCounts::from_reader("3 4\n4\n".as_bytes()),
+            Err(LocationParseError::MissingValue { line: 2 })
+        ));
+        assert!(matches!(
+            LocationCounts::from_reader("3 4 5\n".as_bytes()),
+            Err(LocationParseError::UnexpectedValue { line: 1, value }) if value == "5"
+        ));
+        assert!(matches!(
+            LocationCounts::from_reader("3 4\n\u{feff}4 3\n".as_bytes()),
+            Err(LocationParseError::InvalidNumber { line: 2, value }) if value == "\u{feff}4"
+        ));
+        assert!(matches!(
+            day1_stats("3 4\n\n4 x\n".to_string(), 5),
+            Err(LocationParseError::InvalidNumber { line: 3, value }) if value == "x"
+        ));
+        // Leading blank lines count for both ways of reading the input
+        for result in [
+            LocationCounts::from_reader("\n\n3 4\n4 x".as_bytes()).map(|_| ()),
+            day1_stats("\n\n3 4\n4 x".to_string(), 5).map(|_| ()),
+        ] {
+            assert!(matches!(
+                result,
+                Err(LocationParseError::InvalidNumber { line: 4, value }) if value == "x"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let lists = LocationLists::from_input(EXAMPLE.to_string()).unwrap();
+
+        assert_eq!(Some(&3), lists.left_frequencies().get(&3));
+        assert_eq!(Some(&3), lists.right_frequencies().get(&3));
+        assert_eq!(BTreeSet::from([1, 2]), lists.missing_from_right());
+        assert_eq!(BTreeSet::from([5, 9]), lists.missing_from_left());
+        assert_eq!(vec![0, 1, 1, 2, 2, 5], lists.distances());
+        assert_eq!(Some(1), lists.median_distance());
+        assert_eq!(Some(5), lists.percentile_distance(90.0));
+        assert_eq!(Some(0), lists.percentile_distance(0.0));
+        assert_eq!(
+            vec![
+                SimilarityContribution {
+                    id: 3,
+                    left_count: 3,
+                    right_count: 3,
+                    score: 27
+                },
+                SimilarityContribution {
+                    id: 4,
+                    left_count: 1,
+                    right_count: 1,
+                    score: 4
+                }
+            ],
+            lists.top_similarity_contributors(5)
+        );
+    }
 }
@@ -4,6 +4,8 @@ use std::{
     rc::Rc,
 };
 
+use crate::{profile::phase, util::normalize_input};
+
 type Position = (usize, usize);
 
 #[derive(Debug)]
@@ -25,21 +27,22 @@ struct MapEdge {
 }
 
 pub fn day10_part1(input: String) -> usize {
-    let map = Map::from_input(input);
+    let map = phase("Map::from_input", || Map::from_input(input));
 
-    map.count_paths(false)
+    phase("Map::count_paths", || map.count_paths(false))
 }
 
 pub fn day10_part2(input: String) -> usize {
-    let map = Map::from_input(input);
+    let map = phase("Map::from_input", || Map::from_input(input));
 
-    map.count_paths(true)
+    phase("Map::count_paths", || map.count_paths(true))
 }
 
 impl Map {
     fn from_input(input: String) -> Self {
-        let height = input.trim().lines().count();
-        let width = input.split("\n").nth(0).unwrap().chars().count();
+        let input = normalize_input(&input);
+        let height = input.lines().count();
+        let width = input.lines().nth(0).unwrap().chars().count();
         let input = input.replace("\n", "");
 
         let mut nodes = HashMap::new();
@@ -1,5 +1,11 @@
 use std::collections::HashMap;
 
+use crate::{
+    profile::phase,
+    repl::{parse_optional_arg, Explore, ReplError},
+    util::normalize_input,
+};
+
 #[derive(Debug)]
 struct Game {
     numbers: HashMap<usize, usize>,
@@ -7,7 +13,10 @@ struct Game {
 
 impl Game {
     fn from_input(input: String) -> Self {
-        let numbers = input.trim().split(" ").map(|n| n.parse::<usize>().unwrap());
+        let numbers = normalize_input(&input)
+            .split_whitespace()
+            .map(|n| n.parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
 
         let mut number_counts = HashMap::<usize, usize>::new();
         for number in numbers {
@@ -46,28 +55,60 @@ impl Game {
     }
 }
 
+impl Explore for Game {
+    fn commands(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("blink [n]", "Blink n times (default 1)"),
+            ("count", "Count all stones"),
+            ("distinct", "Count the distinct numbers engraved on stones"),
+        ]
+    }
+
+    fn execute(&mut self, command: &str, args: &[&str]) -> Result<String, ReplError> {
+        match command {
+            "blink" => {
+                for _ in 0..parse_optional_arg(args, 0, 1)? {
+                    *self = self.step();
+                }
+                Ok(String::new())
+            }
+            "count" => Ok(self.count_numbers().to_string()),
+            "distinct" => Ok(self.numbers.len().to_string()),
+            _ => Err(ReplError::UnknownCommand(command.to_string())),
+        }
+    }
+}
+
 fn insert_or_add(map: &mut HashMap<usize, usize>, key: usize, count: usize) {
     map.insert(key, map.get(&key).unwrap_or(&0) + count);
 }
 
 pub fn day11_part1(input: String) -> usize {
-    let mut game = Game::from_input(input);
+    let mut game = phase("Game::from_input", || Game::from_input(input));
 
-    for _ in 0..25 {
-        game = game.step();
-    }
+    phase("Game::step", || {
+        for _ in 0..25 {
+            game = game.step();
+        }
+    });
 
-    game.count_numbers()
+    phase("Game::count_numbers", || game.count_numbers())
 }
 
 pub fn day11_part2(input: String) -> usize {
-    let mut game = Game::from_input(input);
+    let mut game = phase("Game::from_input", || Game::from_input(input));
 
-    for _ in 0..75 {
-        game = game.step();
-    }
+    phase("Game::step", || {
+        for _ in 0..75 {
+            game = game.step();
+        }
+    });
+
+    phase("Game::count_numbers", || game.count_numbers())
+}
 
-    game.count_numbers()
+pub fn day11_repl(input: String) -> Box<dyn Explore> {
+    Box::new(Game::from_input(input))
 }
 
 #[cfg(test)]
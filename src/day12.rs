@@ -1,5 +1,7 @@
 use std::collections::HashSet;
 
+use crate::{profile::phase, util::normalize_input};
+
 type Position = (usize, usize);
 
 #[derive(Debug)]
@@ -78,10 +80,10 @@ impl SharedSides {
 
 impl Map {
     fn parse_input(input: String) -> Self {
-        let input = input.trim();
-        let height = input.split("\n").count();
+        let input = normalize_input(&input);
+        let height = input.lines().count();
         let width = input
-            .split("\n")
+            .lines()
             .take(1)
             .map(|l| l.chars().count())
             .nth(0)
@@ -218,17 +220,19 @@ impl Map {
 }
 
 pub fn day12_part1(input: String) -> usize {
-    let mut map = Map::parse_input(input);
-    map.parse_plots();
+    let mut map = phase("Map::parse_input", || Map::parse_input(input));
+    phase("Map::parse_plots", || map.parse_plots());
 
-    map.calculate_fence_cost()
+    phase("Map::calculate_fence_cost", || map.calculate_fence_cost())
 }
 
 pub fn day12_part2(input: String) -> usize {
-    let mut map = Map::parse_input(input);
-    map.parse_plots();
+    let mut map = phase("Map::parse_input", || Map::parse_input(input));
+    phase("Map::parse_plots", || map.parse_plots());
 
-    map.calculate_fence_cost_discounted()
+    phase("Map::calculate_fence_cost_discounted", || {
+        map.calculate_fence_cost_discounted()
+    })
 }
 
 #[cfg(test)]
@@ -1,3 +1,8 @@
+use crate::{
+    profile::phase,
+    util::{normalize_input, sections},
+};
+
 const A_PRESS_TOKENS: usize = 3;
 const B_PRESS_TOKENS: usize = 1;
 
@@ -131,8 +136,7 @@ struct Arcade {
 impl Arcade {
     fn from_input(input: &str, incorporate_unit_conversion_error: bool) -> Self {
         Self {
-            game_machines: input
-                .split("\n\n")
+            game_machines: sections(&normalize_input(input))
                 .map(|l| GameMachine::from_input(l, incorporate_unit_conversion_error))
                 .collect(),
         }
@@ -147,15 +151,15 @@ impl Arcade {
 }
 
 pub fn day13_part1(input: String) -> usize {
-    let arcade = Arcade::from_input(&input, false);
+    let arcade = phase("Arcade::from_input", || Arcade::from_input(&input, false));
 
-    arcade.get_minimum_tokens()
+    phase("Arcade::get_minimum_tokens", || arcade.get_minimum_tokens())
 }
 
 pub fn day13_part2(input: String) -> usize {
-    let arcade = Arcade::from_input(&input, true);
+    let arcade = phase("Arcade::from_input", || Arcade::from_input(&input, true));
 
-    arcade.get_minimum_tokens()
+    phase("Arcade::get_minimum_tokens", || arcade.get_minimum_tokens())
 }
 
 #[cfg(test)]
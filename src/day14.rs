@@ -1,5 +1,11 @@
 use itertools::Itertools;
 
+use crate::{
+    profile::phase,
+    repl::{parse_optional_arg, Explore, ReplError},
+    util::normalize_input,
+};
+
 #[derive(Debug)]
 struct SecureArea {
     width: usize,
@@ -20,7 +26,10 @@ impl SecureArea {
         SecureArea {
             width,
             height,
-            robots: input.trim().lines().map(Robot::from_input).collect(),
+            robots: normalize_input(input)
+                .lines()
+                .map(Robot::from_input)
+                .collect(),
         }
     }
 
@@ -49,7 +58,8 @@ impl SecureArea {
         false
     }
 
-    fn render(&self) {
+    fn render(&self) -> String {
+        let mut output = String::new();
         for y in 0..(self.height as isize) {
             for x in 0..(self.width as isize) {
                 if self
@@ -57,13 +67,14 @@ impl SecureArea {
                     .iter()
                     .any(|rob| rob.position.0 == x && rob.position.1 == y)
                 {
-                    print!("A");
+                    output.push('A');
                 } else {
-                    print!(" ");
+                    output.push(' ');
                 }
             }
-            println!();
+            output.push('\n');
         }
+        output
     }
 
     fn calculate_safety_factor(&self) -> usize {
@@ -95,9 +106,50 @@ impl SecureArea {
     }
 }
 
+impl Explore for SecureArea {
+    fn commands(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("tick [n]", "Let n seconds pass (default 1)"),
+            ("render", "Show the robot positions"),
+            ("safety", "Calculate the safety factor"),
+            (
+                "tree [max]",
+                "Tick until the robots might form a tree, at most max seconds",
+            ),
+        ]
+    }
+
+    fn execute(&mut self, command: &str, args: &[&str]) -> Result<String, ReplError> {
+        match command {
+            "tick" => {
+                for _ in 0..parse_optional_arg(args, 0, 1)? {
+                    self.tick();
+                }
+                Ok(String::new())
+            }
+            "render" => Ok(self.render()),
+            "safety" => Ok(self.calculate_safety_factor().to_string()),
+            "tree" => {
+                // The robots are back at their start positions after width * height seconds
+                let limit = parse_optional_arg(args, 0, self.width * self.height)?;
+                for seconds in 1..=limit {
+                    self.tick();
+                    if self.might_be_tree() {
+                        return Ok(format!("Tree candidate after {seconds} seconds"));
+                    }
+                }
+                Err(ReplError::Failed(format!(
+                    "No tree candidate within {limit} seconds"
+                )))
+            }
+            _ => Err(ReplError::UnknownCommand(command.to_string())),
+        }
+    }
+}
+
 impl Robot {
     fn from_input(input: &str) -> Self {
-        let parts: Vec<&str> = input.split(" ").collect();
+        let parts: Vec<&str> = input.split_whitespace().collect();
         let position = parts[0].split("=").nth(1).unwrap();
         let velocity = parts[1].split("=").nth(1).unwrap();
 
@@ -108,8 +160,8 @@ impl Robot {
     }
 
     fn tick(&mut self, width: usize, height: usize) {
-        self.position.0 = (self.position.0 + self.velocity.0 + width as isize) % width as isize;
-        self.position.1 = (self.position.1 + self.velocity.1 + height as isize) % height as isize;
+        self.position.0 = (self.position.0 + self.velocity.0).rem_euclid(width as isize);
+        self.position.1 = (self.position.1 + self.velocity.1).rem_euclid(height as isize);
     }
 }
 
@@ -123,37 +175,88 @@ pub fn day14_part1(input: String) -> usize {
 }
 
 fn day14_part1_with_size(input: String, width: usize, height: usize) -> usize {
-    let mut secure_area = SecureArea::from_input(&input, width, height);
+    let mut secure_area = phase("SecureArea::from_input", || {
+        SecureArea::from_input(&input, width, height)
+    });
 
-    for _ in 0..100 {
-        secure_area.tick();
-    }
+    phase("SecureArea::tick", || {
+        for _ in 0..100 {
+            secure_area.tick();
+        }
+    });
 
-    secure_area.calculate_safety_factor()
+    phase("SecureArea::calculate_safety_factor", || {
+        secure_area.calculate_safety_factor()
+    })
 }
 
 pub fn day14_part2(input: String) -> usize {
-    let mut secure_area = SecureArea::from_input(&input, 101, 103);
+    let mut secure_area = phase("SecureArea::from_input", || {
+        SecureArea::from_input(&input, 101, 103)
+    });
 
-    let mut i = 0;
-    loop {
-        i += 1;
+    let i = phase("SecureArea::might_be_tree", || {
+        let mut i = 0;
+        loop {
+            i += 1;
 
-        secure_area.tick();
+            secure_area.tick();
 
-        if secure_area.might_be_tree() {
-            secure_area.render();
-
-            break;
+            if secure_area.might_be_tree() {
+                break i;
+            }
         }
-    }
+    });
+    print!("{}", phase("SecureArea::render", || secure_area.render()));
 
     i
 }
 
+pub fn day14_repl(input: String) -> Box<dyn Explore> {
+    Box::new(SecureArea::from_input(&input, 101, 103))
+}
+
 #[cfg(test)]
 mod test {
-    use crate::day14::day14_part1_with_size;
+    use crate::{
+        day14::{day14_part1_with_size, SecureArea},
+        repl::{Explore, ReplError},
+        snapshot::assert_snapshot,
+    };
+
+    use proptest::prelude::*;
+
+    use super::Robot;
+
+    proptest! {
+        #[test]
+        fn robots_stay_within_bounds(
+            width in 1..120usize,
+            height in 1..120usize,
+            robots in prop::collection::vec((0.0..1.0f64, 0.0..1.0f64, -100..100isize, -100..100isize), 1..20),
+            seconds in 0..200usize,
+        ) {
+            let mut secure_area = SecureArea {
+                width,
+                height,
+                robots: robots
+                    .into_iter()
+                    .map(|(x, y, vx, vy)| Robot {
+                        position: ((x * width as f64) as isize, (y * height as f64) as isize),
+                        velocity: (vx, vy),
+                    })
+                    .collect(),
+            };
+
+            for _ in 0..seconds {
+                secure_area.tick();
+                for robot in &secure_area.robots {
+                    prop_assert!((0..width as isize).contains(&robot.position.0));
+                    prop_assert!((0..height as isize).contains(&robot.position.1));
+                }
+            }
+        }
+    }
 
     #[test]
     fn test_part1() {
@@ -178,4 +281,58 @@ p=9,5 v=-3,-3"#
             )
         )
     }
+
+    #[test]
+    fn test_render_after_100_seconds() {
+        let mut secure_area = SecureArea::from_input(
+            r#"p=0,4 v=3,-3
+p=6,3 v=-1,-3
+p=10,3 v=-1,2
+p=2,0 v=2,-1
+p=0,0 v=1,3
+p=3,0 v=-2,-2
+p=7,6 v=-1,-3
+p=3,0 v=-1,-2
+p=9,3 v=2,3
+p=7,3 v=-1,2
+p=2,4 v=2,-3
+p=9,5 v=-3,-3"#,
+            11,
+            7,
+        );
+        for _ in 0..100 {
+            secure_area.tick();
+        }
+
+        assert_snapshot("day14_after_100_seconds", &secure_area.render());
+    }
+
+    #[test]
+    fn test_tick_wraps_fast_robots() {
+        let mut robot = Robot {
+            position: (2, 1),
+            velocity: (-25, -16),
+        };
+        robot.tick(11, 7);
+
+        assert_eq!((10, 6), robot.position);
+    }
+
+    #[test]
+    fn test_tree_gives_up() {
+        let mut secure_area = SecureArea::from_input("p=0,0 v=1,1", 11, 7);
+
+        assert_eq!(
+            Err(ReplError::Failed(
+                "No tree candidate within 77 seconds".to_string()
+            )),
+            secure_area.execute("tree", &[])
+        );
+        assert_eq!(
+            Err(ReplError::Failed(
+                "No tree candidate within 5 seconds".to_string()
+            )),
+            secure_area.execute("tree", &["5"])
+        );
+    }
 }
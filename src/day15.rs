@@ -1,7 +1,12 @@
-use std::{collections::HashSet, fmt::Display};
+use std::{collections::HashSet, fmt::Display, str::FromStr};
 
 use log::warn;
 
+use crate::{
+    profile::phase,
+    util::{normalize_input, parse_sections},
+};
+
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 struct Position {
     x: usize,
@@ -22,47 +27,37 @@ enum Direction {
     West,
 }
 
-impl From<char> for Direction {
-    fn from(value: char) -> Self {
+impl TryFrom<char> for Direction {
+    type Error = String;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
         match value {
-            '^' => Self::North,
-            '>' => Self::East,
-            'v' => Self::South,
-            '<' => Self::West,
-            _ => panic!("Unknown direction: {}", value),
+            '^' => Ok(Self::North),
+            '>' => Ok(Self::East),
+            'v' => Ok(Self::South),
+            '<' => Ok(Self::West),
+            _ => Err(format!("Unknown direction: {}", value)),
         }
     }
 }
 
-struct Warehouse {
+/// The warehouse map section as drawn, one cell per character
+struct Layout {
     robot: Position,
     boxes: HashSet<Position>,
     walls: HashSet<Position>,
-
-    steps: Vec<Direction>,
-
-    width: usize,
-    height: usize,
-    large_boxes: bool,
 }
 
-impl Warehouse {
-    fn from_input(input: &str, wide_layout: bool) -> Self {
-        let parts: Vec<&str> = input.trim().split("\n\n").collect();
-        let map = parts[0];
-        let steps: Vec<Direction> = parts[1]
-            .replace("\n", "")
-            .chars()
-            .map(Direction::from)
-            .rev()
-            .collect();
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut walls = HashSet::new();
         let mut boxes = HashSet::new();
-        let mut robot_position = Position { x: 0, y: 0 };
+        let mut robot = Position { x: 0, y: 0 };
 
-        for (y, line) in map.lines().enumerate() {
+        for (y, line) in s.lines().enumerate() {
             for (x, char) in line.chars().enumerate() {
-                let x = if wide_layout { x * 2 } else { x };
                 match char {
                     '#' => {
                         walls.insert(Position { x, y });
@@ -71,18 +66,65 @@ impl Warehouse {
                         boxes.insert(Position { x, y });
                     }
                     '@' => {
-                        robot_position = Position { x, y };
+                        robot = Position { x, y };
                     }
                     '.' => {}
-                    _ => {
-                        panic!("Invalid map input: {}", char);
-                    }
+                    _ => return Err(format!("Invalid map input: {}", char)),
                 }
             }
         }
 
+        Ok(Self {
+            robot,
+            boxes,
+            walls,
+        })
+    }
+}
+
+/// The robot's moves in the order they are made
+struct Steps(Vec<Direction>);
+
+impl FromStr for Steps {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars()
+            .filter(|char| *char != '\n')
+            .map(Direction::try_from)
+            .collect::<Result<_, _>>()
+            .map(Steps)
+    }
+}
+
+struct Warehouse {
+    robot: Position,
+    boxes: HashSet<Position>,
+    walls: HashSet<Position>,
+
+    steps: Vec<Direction>,
+
+    width: usize,
+    height: usize,
+    large_boxes: bool,
+}
+
+impl Warehouse {
+    fn from_input(input: &str, wide_layout: bool) -> Self {
+        let input = normalize_input(input);
+        let (layout, Steps(mut steps)): (Layout, Steps) =
+            parse_sections(&input).unwrap_or_else(|e| panic!("{e}"));
+        steps.reverse();
+
+        let widen = |Position { x, y }| Position {
+            x: if wide_layout { x * 2 } else { x },
+            y,
+        };
+        let walls: HashSet<Position> = layout.walls.into_iter().map(widen).collect();
+        let boxes: HashSet<Position> = layout.boxes.into_iter().map(widen).collect();
+
         Self {
-            robot: robot_position,
+            robot: widen(layout.robot),
             width: walls.iter().map(|wall| wall.x).max().unwrap_or(0),
             height: walls.iter().map(|wall| wall.y).max().unwrap_or(0),
             boxes,
@@ -201,55 +243,191 @@ impl Warehouse {
         self.boxes.iter().map(|pos| pos.x + 100 * pos.y).sum()
     }
 
-    fn print(&self) {
+    fn render(&self) -> String {
+        let mut output = String::new();
+        // Walls of the wide layout are stored by their left half only
+        let width = self.width + usize::from(self.large_boxes);
         for y in 0..=self.height {
-            for x in 0..=self.width {
+            for x in 0..=width {
                 let position = Position { x, y };
                 if self.walls.contains(&position)
                     || (x > 0 && self.large_boxes && self.walls.contains(&Position { x: x - 1, y }))
                 {
-                    print!("#");
+                    output.push('#');
                 } else if !self.large_boxes && self.boxes.contains(&position) {
-                    print!("O");
+                    output.push('O');
                 } else if self.large_boxes && self.boxes.contains(&position) {
-                    print!("[");
+                    output.push('[');
                 } else if self.large_boxes
                     && x > 0
                     && self.boxes.contains(&Position { x: x - 1, y })
                 {
-                    print!("]");
+                    output.push(']');
                 } else if self.robot == position {
-                    print!("@");
+                    output.push('@');
                 } else {
-                    print!(".");
+                    output.push('.');
                 }
             }
-            println!();
+            output.push('\n');
         }
+        output
     }
 }
 
 pub fn day15_part1(input: String) -> usize {
-    let mut warehouse = Warehouse::from_input(&input, false);
+    let mut warehouse = phase("Warehouse::from_input", || {
+        Warehouse::from_input(&input, false)
+    });
 
-    warehouse.simulate();
-    warehouse.print();
+    phase("Warehouse::simulate", || warehouse.simulate());
+    print!("{}", phase("Warehouse::render", || warehouse.render()));
 
-    warehouse.calculate_gps_coordinates()
+    phase("Warehouse::calculate_gps_coordinates", || {
+        warehouse.calculate_gps_coordinates()
+    })
 }
 
 pub fn day15_part2(input: String) -> usize {
-    let mut warehouse = Warehouse::from_input(&input, true);
+    let mut warehouse = phase("Warehouse::from_input", || {
+        Warehouse::from_input(&input, true)
+    });
 
-    warehouse.simulate();
-    warehouse.print();
+    phase("Warehouse::simulate", || warehouse.simulate());
+    print!("{}", phase("Warehouse::render", || warehouse.render()));
 
-    warehouse.calculate_gps_coordinates()
+    phase("Warehouse::calculate_gps_coordinates", || {
+        warehouse.calculate_gps_coordinates()
+    })
 }
 
 #[cfg(test)]
 mod test {
-    use crate::day15::{day15_part1, day15_part2};
+    use crate::{
+        day15::{day15_part1, day15_part2, Warehouse},
+        snapshot::assert_snapshot,
+    };
+
+    use std::collections::HashSet;
+
+    use proptest::prelude::*;
+
+    use super::{Direction, Position};
+
+    /// Cells covered by the given positions, taking the wide layout into account.
+    fn cells(positions: &HashSet<Position>, wide: bool) -> Vec<Position> {
+        positions
+            .iter()
+            .flat_map(|position| {
+                let mut cells = vec![position.clone()];
+                if wide {
+                    cells.push(Position {
+                        x: position.x + 1,
+                        y: position.y,
+                    });
+                }
+                cells
+            })
+            .collect()
+    }
+
+    fn warehouse_input() -> impl Strategy<Value = String> {
+        (3..10usize, 3..10usize)
+            .prop_flat_map(|(width, height)| {
+                (
+                    Just(width),
+                    prop::collection::vec(
+                        prop::sample::select(vec!['.', '.', '.', 'O', 'O', '#']),
+                        width * height,
+                    ),
+                    0..width * height,
+                    prop::collection::vec(prop::sample::select(vec!['^', '>', 'v', '<']), 1..40),
+                )
+            })
+            .prop_map(|(width, mut cells, robot, steps)| {
+                cells[robot] = '@';
+                let border = "#".repeat(width + 2);
+                let rows = cells
+                    .chunks(width)
+                    .map(|row| format!("#{}#", row.iter().collect::<String>()))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!(
+                    "{border}\n{rows}\n{border}\n\n{}",
+                    steps.into_iter().collect::<String>()
+                )
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn simulate_never_overlaps(input in warehouse_input(), wide: bool) {
+            let mut warehouse = Warehouse::from_input(&input, wide);
+            let steps: Vec<Direction> = warehouse.steps.drain(..).rev().collect();
+            let box_count = warehouse.boxes.len();
+
+            for step in steps {
+                warehouse.steps.push(step);
+                warehouse.simulate();
+
+                let mut occupied = HashSet::new();
+                for cell in cells(&warehouse.boxes, wide)
+                    .into_iter()
+                    .chain(cells(&warehouse.walls, wide))
+                    .chain([warehouse.robot.clone()])
+                {
+                    prop_assert!(occupied.insert(cell.clone()), "{} is occupied twice", cell);
+                }
+                prop_assert_eq!(box_count, warehouse.boxes.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_small() {
+        let mut warehouse = Warehouse::from_input(
+            r#"########
+#..O.O.#
+##@.O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########
+
+<^^>>>vv<v>>v<<"#,
+            false,
+        );
+        warehouse.simulate();
+
+        assert_snapshot("day15_small", &warehouse.render());
+    }
+
+    #[test]
+    fn test_render_small_wide() {
+        let mut warehouse = Warehouse::from_input(
+            r#"#######
+#...#.#
+#.....#
+#..OO@#
+#..O..#
+#.....#
+#######
+
+<vv<<^^<<^^"#,
+            true,
+        );
+        warehouse.simulate();
+
+        assert_snapshot("day15_small_wide", &warehouse.render());
+    }
+
+    #[test]
+    fn test_render_wide_right_wall() {
+        let warehouse = Warehouse::from_input("####\n#@O#\n####\n\n<", true);
+
+        assert_eq!("########\n##@.[]##\n########\n", warehouse.render());
+    }
 
     #[test]
     fn test_part1() {
@@ -4,6 +4,8 @@ use std::{
     vec,
 };
 
+use crate::{profile::phase, util::normalize_input};
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 struct Position {
     x: usize,
@@ -169,7 +171,8 @@ impl Direction {
 
 impl Maze {
     fn from_input(input: &str) -> Self {
-        let lines = input.trim().lines();
+        let input = normalize_input(input);
+        let lines = input.lines();
         let height = lines.clone().count();
         let width = lines
             .into_iter()
@@ -218,17 +221,17 @@ impl Maze {
 }
 
 pub fn day16_part1(input: String) -> usize {
-    let maze = Maze::from_input(&input);
+    let maze = phase("Maze::from_input", || Maze::from_input(&input));
     let mut solver = Solver {
         mazes: vec![maze],
         visited: HashMap::new(),
     };
 
-    solver.solve()[0].score
+    phase("Solver::solve", || solver.solve())[0].score
 }
 
 pub fn day16_part2(input: String) -> usize {
-    let maze = Maze::from_input(&input);
+    let maze = phase("Maze::from_input", || Maze::from_input(&input));
     let mut solver = Solver {
         mazes: vec![maze],
         visited: HashMap::new(),
@@ -236,7 +239,7 @@ pub fn day16_part2(input: String) -> usize {
 
     let mut positions: HashSet<Position> = HashSet::new();
 
-    let solved_mazes = solver.solve();
+    let solved_mazes = phase("Solver::solve", || solver.solve());
     for maze in solved_mazes {
         for visited in maze.visited_positions {
             positions.insert(visited);
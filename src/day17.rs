@@ -2,6 +2,12 @@ use core::panic;
 
 use itertools::Itertools;
 
+use crate::{
+    profile::phase,
+    repl::{parse_arg, Explore, ReplError},
+    util::normalize_input,
+};
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum Operator {
     DivisionA,
@@ -14,7 +20,7 @@ enum Operator {
     Mod8Output,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 struct Instruction {
     operator: Operator,
     operand: u8,
@@ -35,8 +41,9 @@ struct Computer {
 
 impl Computer {
     fn from_input(input: &str) -> Self {
+        let input = normalize_input(input);
         let lines: Vec<&str> = input
-            .split("\n")
+            .lines()
             .map(|l| l.split(":").nth(1))
             .filter(|val| val.is_some())
             .map(|val| val.unwrap().trim())
@@ -71,29 +78,41 @@ impl Computer {
         }
     }
 
-    fn evaluate_next_output(&mut self) -> Option<u64> {
-        let instructions = self.instructions.clone();
-        while let Some(instruction) = instructions.get(self.ip / 2) {
-            match (&instruction.operator, instruction.operand) {
-                (Operator::DivisionA, operand) => self.div_a(operand),
-                (Operator::DivisionB, operand) => self.div_b(operand),
-                (Operator::DivisionC, operand) => self.div_c(operand),
-                (Operator::Xor, operator) => self.b ^= operator as u64,
-                (Operator::RegisterXOR, _) => self.b ^= self.c,
-                (Operator::Mod8, operand) => self.b = self.parse_combo_operand(operand) % 8,
-                (Operator::Mod8Output, operand) => {
-                    let out = self.parse_combo_operand(operand) % 8;
-                    self.output.push(out);
-                    self.ip += 2;
-                    return Some(out);
-                }
-                (Operator::JumpNonZero, _) => {}
+    /// Execute the instruction at the instruction pointer.
+    /// Returns `false` if the program already halted.
+    fn step(&mut self) -> bool {
+        let Some(instruction) = self.instructions.get(self.ip / 2).copied() else {
+            return false;
+        };
+
+        match (&instruction.operator, instruction.operand) {
+            (Operator::DivisionA, operand) => self.div_a(operand),
+            (Operator::DivisionB, operand) => self.div_b(operand),
+            (Operator::DivisionC, operand) => self.div_c(operand),
+            (Operator::Xor, operator) => self.b ^= operator as u64,
+            (Operator::RegisterXOR, _) => self.b ^= self.c,
+            (Operator::Mod8, operand) => self.b = self.parse_combo_operand(operand) % 8,
+            (Operator::Mod8Output, operand) => {
+                let out = self.parse_combo_operand(operand) % 8;
+                self.output.push(out);
             }
+            (Operator::JumpNonZero, _) => {}
+        }
 
-            if instruction.operator == Operator::JumpNonZero && self.a != 0 {
-                self.ip = instruction.operand as usize;
-            } else {
-                self.ip += 2;
+        if instruction.operator == Operator::JumpNonZero && self.a != 0 {
+            self.ip = instruction.operand as usize;
+        } else {
+            self.ip += 2;
+        }
+
+        true
+    }
+
+    fn evaluate_next_output(&mut self) -> Option<u64> {
+        let output_count = self.output.len();
+        while self.step() {
+            if self.output.len() > output_count {
+                return self.output.last().copied();
             }
         }
         None
@@ -103,6 +122,10 @@ impl Computer {
         while self.evaluate_next_output().is_some() {}
     }
 
+    fn registers(&self) -> String {
+        format!("A={} B={} C={} IP={}", self.a, self.b, self.c, self.ip)
+    }
+
     /*
      * The adv instruction (opcode 0) performs division. The numerator is the value in the A register.
      * The denominator is found by raising 2 to the power of the instruction's combo operand.
@@ -141,6 +164,52 @@ impl Computer {
     }
 }
 
+impl Explore for Computer {
+    fn commands(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("step", "Execute the next instruction"),
+            ("run", "Execute until the program halts"),
+            ("regs", "Show the registers and the instruction pointer"),
+            ("output", "Show the output so far"),
+            ("set <reg> <value>", "Set register a, b or c"),
+        ]
+    }
+
+    fn execute(&mut self, command: &str, args: &[&str]) -> Result<String, ReplError> {
+        match command {
+            "step" => match self.instructions.get(self.ip / 2).copied() {
+                Some(instruction) => {
+                    self.step();
+                    Ok(format!(
+                        "{:?} {}\n{}",
+                        instruction.operator,
+                        instruction.operand,
+                        self.registers()
+                    ))
+                }
+                None => Ok("Program halted".to_string()),
+            },
+            "run" => {
+                self.evaluate();
+                Ok(self.output.iter().join(","))
+            }
+            "regs" => Ok(self.registers()),
+            "output" => Ok(self.output.iter().join(",")),
+            "set" => {
+                let value = parse_arg(args, 1, "value")?;
+                match parse_arg::<String>(args, 0, "reg")?.as_str() {
+                    "a" => self.a = value,
+                    "b" => self.b = value,
+                    "c" => self.c = value,
+                    reg => return Err(ReplError::InvalidArgument(reg.to_string())),
+                }
+                Ok(self.registers())
+            }
+            _ => Err(ReplError::UnknownCommand(command.to_string())),
+        }
+    }
+}
+
 impl From<&str> for Operator {
     fn from(value: &str) -> Self {
         Operator::from(value.parse::<u8>().unwrap())
@@ -164,15 +233,15 @@ impl From<u8> for Operator {
 }
 
 pub fn day17_part1(input: String) -> String {
-    let mut computer = Computer::from_input(&input);
+    let mut computer = phase("Computer::from_input", || Computer::from_input(&input));
 
-    computer.evaluate();
+    phase("Computer::evaluate", || computer.evaluate());
 
     computer.output.into_iter().join(",").to_string()
 }
 
 pub fn day17_part2(input: String) -> usize {
-    let computer = Computer::from_input(&input);
+    let computer = phase("Computer::from_input", || Computer::from_input(&input));
 
     let target_len = computer.code.len();
     let mut a = 0;
@@ -201,6 +270,10 @@ pub fn day17_part2(input: String) -> usize {
     }
 }
 
+pub fn day17_repl(input: String) -> Box<dyn Explore> {
+    Box::new(Computer::from_input(&input))
+}
+
 #[cfg(test)]
 mod test {
     use crate::day17::{day17_part1, Computer};
@@ -2,6 +2,8 @@ use std::collections::{HashMap, HashSet};
 
 use itertools::Itertools;
 
+use crate::{profile::phase, util::normalize_input};
+
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 struct Position {
     x: usize,
@@ -32,7 +34,10 @@ impl Memory {
     fn from_input(input: String, width: usize, height: usize) -> Self {
         Memory {
             corrupted_memory_areas: HashSet::new(),
-            falling_bytes: input.trim().lines().map(Position::from).collect(),
+            falling_bytes: normalize_input(&input)
+                .lines()
+                .map(Position::from)
+                .collect(),
             height,
             width,
             position: Position {
@@ -122,10 +127,12 @@ pub fn day18_part1(input: String) -> usize {
 }
 
 fn execute_part1(input: String, width: usize, height: usize, bytes: usize) -> usize {
-    let mut memory = Memory::from_input(input, width, height);
-    memory.drop_bytes(bytes);
+    let mut memory = phase("Memory::from_input", || {
+        Memory::from_input(input, width, height)
+    });
+    phase("Memory::drop_bytes", || memory.drop_bytes(bytes));
 
-    memory.shortest_path()
+    phase("Memory::shortest_path", || memory.shortest_path())
 }
 
 pub fn day18_part2(input: String) -> String {
@@ -133,9 +140,11 @@ pub fn day18_part2(input: String) -> String {
 }
 
 fn execute_part2(input: String, width: usize, height: usize) -> String {
-    let memory: Memory = Memory::from_input(input, width, height);
+    let memory: Memory = phase("Memory::from_input", || {
+        Memory::from_input(input, width, height)
+    });
     let max = memory.falling_bytes.len();
-    bin_search(&memory, 0, max - 1)
+    phase("bin_search", || bin_search(&memory, 0, max - 1))
 }
 
 fn bin_search(memory: &Memory, min: usize, max: usize) -> String {
@@ -1,10 +1,16 @@
 use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
+    str::FromStr,
 };
 
 use itertools::Itertools;
 
+use crate::{
+    profile::phase,
+    util::{normalize_input, parse_sections},
+};
+
 struct Onsen {
     towel_prefixes: HashMap<char, Vec<String>>,
     designs: Vec<String>,
@@ -12,24 +18,51 @@ struct Onsen {
     possible_designs: RefCell<HashMap<String, usize>>,
 }
 
+/// The comma separated towel patterns
+struct Towels(Vec<String>);
+
+impl FromStr for Towels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(", ")
+            .map(|towel| match towel.trim() {
+                "" => Err("Empty towel pattern".to_string()),
+                towel => Ok(towel.to_string()),
+            })
+            .collect::<Result<_, _>>()
+            .map(Towels)
+    }
+}
+
+/// The desired designs, one per line
+struct Designs(Vec<String>);
+
+impl FromStr for Designs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Designs(s.lines().map(|l| l.to_string()).collect()))
+    }
+}
+
 impl Onsen {
     fn from_input(input: String) -> Self {
-        let parts: Vec<&str> = input.trim().split("\n\n").collect();
+        let input = normalize_input(&input);
+        let (Towels(available_towels), Designs(designs)) =
+            parse_sections(&input).unwrap_or_else(|e| panic!("{e}"));
 
-        let available_towels: Vec<&str> = parts[0].split(", ").collect();
         let mut towel_prefixes: HashMap<char, Vec<String>> = HashMap::new();
 
         for available_towel in available_towels {
             let prefix: char = available_towel.chars().nth(0).unwrap();
             if let Some(existing_list) = towel_prefixes.get_mut(&prefix) {
-                existing_list.push(available_towel.to_string());
+                existing_list.push(available_towel);
             } else {
-                towel_prefixes.insert(prefix, vec![available_towel.to_string()]);
+                towel_prefixes.insert(prefix, vec![available_towel]);
             }
         }
 
-        let designs = parts[1].lines().map(|l| l.to_string()).collect();
-
         Self {
             towel_prefixes,
             designs,
@@ -95,15 +128,19 @@ impl Onsen {
 }
 
 pub fn day19_part1(input: String) -> usize {
-    let onsen = Onsen::from_input(input);
+    let onsen = phase("Onsen::from_input", || Onsen::from_input(input));
 
-    onsen.count_possible_designs()
+    phase("Onsen::count_possible_designs", || {
+        onsen.count_possible_designs()
+    })
 }
 
 pub fn day19_part2(input: String) -> usize {
-    let onsen = Onsen::from_input(input);
+    let onsen = phase("Onsen::from_input", || Onsen::from_input(input));
 
-    onsen.count_possible_design_positions()
+    phase("Onsen::count_possible_design_positions", || {
+        onsen.count_possible_design_positions()
+    })
 }
 
 #[cfg(test)]
@@ -1,8 +1,14 @@
-use itertools::Itertools;
-use std::{num::ParseIntError, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write},
+    num::ParseIntError,
+    str::FromStr,
+};
 
 use log::error;
 
+use crate::{profile::phase, util::normalize_input};
+
 struct Report {
     levels: Vec<usize>,
 }
@@ -19,60 +25,246 @@ impl From<ParseIntError> for ReportParsingError {
     }
 }
 
-impl Report {
-    fn is_safe(&self, enable_problem_dampener: bool) -> bool {
-        assert!(self.levels.len() > 1);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Trend {
+    Ascending,
+    Descending,
+}
 
-        let is_ascending = self.levels[1] > self.levels[0];
+/// Direction of the levels so far and whether it already changed once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct TrendState {
+    trend: Option<Trend>,
+    changed: bool,
+}
 
-        for (i, pair) in self
-            .levels
-            .clone()
-            .into_iter()
-            .tuple_windows::<(_, _)>()
-            .enumerate()
-        {
-            if is_ascending && pair.1 < pair.0 {
-                if enable_problem_dampener {
-                    return self.is_save_with_problem_dampener(i);
-                } else {
-                    return false;
+/// Rules a report has to follow to be considered safe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SafetyPolicy {
+    /// Smallest allowed difference between adjacent levels
+    min_step: usize,
+    /// Largest allowed difference between adjacent levels
+    max_step: usize,
+    /// Allow adjacent levels to be equal
+    allow_plateaus: bool,
+    /// Allow the levels to change direction once
+    allow_direction_change: bool,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        Self {
+            min_step: 1,
+            max_step: 3,
+            allow_plateaus: false,
+            allow_direction_change: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    Plateau,
+    DirectionChange,
+    StepTooSmall,
+    StepTooLarge,
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::Plateau => write!(f, "levels must not stay the same"),
+            Rule::DirectionChange => write!(f, "levels must not change direction"),
+            Rule::StepTooSmall => write!(f, "step is too small"),
+            Rule::StepTooLarge => write!(f, "step is too large"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Verdict {
+    Safe,
+    /// The first pair of adjacent levels breaking a rule, with the index of its first level
+    Unsafe {
+        index: usize,
+        levels: (usize, usize),
+        rule: Rule,
+    },
+}
+
+impl SafetyPolicy {
+    /// A policy allowing adjacent levels to differ by `min_step..=max_step`.
+    pub fn new(min_step: usize, max_step: usize) -> Result<Self, String> {
+        if min_step > max_step {
+            return Err(format!(
+                "The minimum step {min_step} is larger than the maximum step {max_step}"
+            ));
+        }
+
+        Ok(Self {
+            min_step,
+            max_step,
+            ..Self::default()
+        })
+    }
+
+    pub fn allow_plateaus(mut self, allow_plateaus: bool) -> Self {
+        self.allow_plateaus = allow_plateaus;
+        self
+    }
+
+    pub fn allow_direction_change(mut self, allow_direction_change: bool) -> Self {
+        self.allow_direction_change = allow_direction_change;
+        self
+    }
+
+    /// Check the step from `level` to `next_level`, returning the trend after it.
+    fn check_step(
+        &self,
+        state: TrendState,
+        level: usize,
+        next_level: usize,
+    ) -> Result<TrendState, Rule> {
+        if level == next_level {
+            return match self.allow_plateaus {
+                true => Ok(state),
+                false => Err(Rule::Plateau),
+            };
+        }
+
+        let trend = if next_level > level {
+            Trend::Ascending
+        } else {
+            Trend::Descending
+        };
+        let next_state = match state.trend {
+            Some(current) if current != trend => {
+                if !self.allow_direction_change || state.changed {
+                    return Err(Rule::DirectionChange);
                 }
-            }
-            if !is_ascending && pair.1 > pair.0 {
-                if enable_problem_dampener {
-                    return self.is_save_with_problem_dampener(i);
-                } else {
-                    return false;
+                TrendState {
+                    trend: Some(trend),
+                    changed: true,
                 }
             }
-            let distance = pair.0.abs_diff(pair.1);
-            if !(1..=3).contains(&distance) {
-                if enable_problem_dampener {
-                    return self.is_save_with_problem_dampener(i);
-                } else {
-                    return false;
+            _ => TrendState {
+                trend: Some(trend),
+                changed: state.changed,
+            },
+        };
+
+        let distance = level.abs_diff(next_level);
+        if distance < self.min_step {
+            Err(Rule::StepTooSmall)
+        } else if distance > self.max_step {
+            Err(Rule::StepTooLarge)
+        } else {
+            Ok(next_state)
+        }
+    }
+}
+
+impl Report {
+    fn is_safe(&self, enable_problem_dampener: bool) -> bool {
+        let policy = SafetyPolicy::default();
+
+        if enable_problem_dampener {
+            self.is_safe_with_tolerance(1, &policy).is_some()
+        } else {
+            self.check(&policy) == Verdict::Safe
+        }
+    }
+
+    fn check(&self, policy: &SafetyPolicy) -> Verdict {
+        let mut state = TrendState::default();
+
+        for (index, pair) in self.levels.windows(2).enumerate() {
+            match policy.check_step(state, pair[0], pair[1]) {
+                Ok(next_state) => state = next_state,
+                Err(rule) => {
+                    return Verdict::Unsafe {
+                        index,
+                        levels: (pair[0], pair[1]),
+                        rule,
+                    }
                 }
             }
         }
 
-        true
+        Verdict::Safe
     }
 
-    fn is_save_with_problem_dampener(&self, index: usize) -> bool {
-        (index > 0 && self.is_safe_with_removal(index - 1))
-            || self.is_safe_with_removal(index)
-            || self.is_safe_with_removal(index + 1)
-    }
+    /// Check whether removing at most `tolerance` levels makes the report safe.
+    /// Returns the indices of the levels to remove, or `None` if that is not possible.
+    ///
+    /// Runs in O(n * tolerance): for every kept level and trend state only the
+    /// fewest removals before it are tracked, reachable from the last
+    /// `tolerance + 1` levels.
+    fn is_safe_with_tolerance(
+        &self,
+        tolerance: usize,
+        policy: &SafetyPolicy,
+    ) -> Option<Vec<usize>> {
+        type Best = BTreeMap<TrendState, (usize, Option<(usize, TrendState)>)>;
 
-    fn is_safe_with_removal(&self, index: usize) -> bool {
-        if self.levels.len() <= index {
-            return false;
+        let len = self.levels.len();
+        // Like `check`, a report without levels is safe
+        if len == 0 {
+            return Some(Vec::new());
         }
-        let mut new_levels = self.levels.clone();
-        new_levels.remove(index);
+        let mut best: Vec<Best> = vec![Best::new(); len];
 
-        Report { levels: new_levels }.is_safe(false)
+        for i in 0..len {
+            let (previous, current) = best.split_at_mut(i);
+            let current = &mut current[0];
+            if i <= tolerance {
+                current.insert(TrendState::default(), (i, None));
+            }
+
+            for (j, states) in previous
+                .iter()
+                .enumerate()
+                .skip(i.saturating_sub(tolerance + 1))
+            {
+                for (state, (removed, _)) in states {
+                    let removed = removed + (i - j - 1);
+                    if removed > tolerance {
+                        continue;
+                    }
+                    if let Ok(next_state) =
+                        policy.check_step(*state, self.levels[j], self.levels[i])
+                    {
+                        let entry = current.entry(next_state).or_insert((usize::MAX, None));
+                        if removed < entry.0 {
+                            *entry = (removed, Some((j, *state)));
+                        }
+                    }
+                }
+            }
+        }
+
+        let (mut last_kept, _) = best
+            .iter()
+            .enumerate()
+            .flat_map(|(i, states)| {
+                states
+                    .iter()
+                    .map(move |(state, (removed, _))| ((i, *state), removed + len - 1 - i))
+            })
+            .filter(|(_, removed)| *removed <= tolerance)
+            .min_by_key(|(_, removed)| *removed)?;
+
+        let mut kept = vec![false; len];
+        loop {
+            let (i, state) = last_kept;
+            kept[i] = true;
+            match best[i][&state].1 {
+                Some(previous) => last_kept = previous,
+                None => break,
+            }
+        }
+
+        Some((0..len).filter(|i| !kept[*i]).collect())
     }
 }
 
@@ -80,7 +272,7 @@ impl FromStr for Report {
     type Err = ReportParsingError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let levels = s
-            .split(" ")
+            .split_whitespace()
             .map(|v| v.parse::<usize>())
             .collect::<Result<Vec<usize>, ParseIntError>>()?;
         Ok(Self { levels })
@@ -88,26 +280,280 @@ impl FromStr for Report {
 }
 
 pub fn day2_part1(input: String) -> usize {
-    let reports = parse_input(input).unwrap();
+    let reports = phase("parse_input", || parse_input(input).unwrap());
 
-    reports
-        .into_iter()
-        .filter(|report| report.is_safe(false))
-        .count()
+    phase("Report::is_safe", || {
+        reports
+            .into_iter()
+            .filter(|report| report.is_safe(false))
+            .count()
+    })
 }
 
 pub fn day2_part2(input: String) -> usize {
-    let reports = parse_input(input).unwrap();
+    let reports = phase("parse_input", || parse_input(input).unwrap());
+
+    phase("Report::is_safe", || {
+        reports
+            .into_iter()
+            .filter(|report| report.is_safe(true))
+            .count()
+    })
+}
+
+pub fn day2_with_tolerance(input: String, tolerance: usize, policy: SafetyPolicy) -> usize {
+    let reports = phase("parse_input", || parse_input(input).unwrap());
+
+    phase("Report::is_safe_with_tolerance", || {
+        reports
+            .into_iter()
+            .filter(|report| report.is_safe_with_tolerance(tolerance, &policy).is_some())
+            .count()
+    })
+}
+
+pub fn day2_check(input: String, policy: SafetyPolicy) -> String {
+    let reports = phase("parse_input", || parse_input(input).unwrap());
 
-    reports
-        .into_iter()
-        .filter(|report| report.is_safe(true))
-        .count()
+    phase("Report::check", || {
+        let mut output = String::new();
+        let mut safe = 0;
+        for (n, report) in reports.iter().enumerate() {
+            match report.check(&policy) {
+                Verdict::Safe => safe += 1,
+                Verdict::Unsafe {
+                    index,
+                    levels,
+                    rule,
+                } => writeln!(
+                    output,
+                    "Report {}: {} -> {} at levels {}-{}: {rule}",
+                    n + 1,
+                    levels.0,
+                    levels.1,
+                    index + 1,
+                    index + 2
+                )
+                .unwrap(),
+            }
+        }
+        writeln!(output, "{safe} of {} reports are safe", reports.len()).unwrap();
+        output
+    })
 }
 
 fn parse_input(input: String) -> Result<Vec<Report>, ReportParsingError> {
-    let reports = input.trim().split("\n");
-    let reports = reports.map(Report::from_str);
+    let input = normalize_input(&input);
+    let reports = input
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(Report::from_str);
 
     reports.collect()
 }
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use proptest::prelude::*;
+
+    use crate::day2::{
+        day2_check, day2_part1, day2_part2, day2_with_tolerance, Report, Rule, SafetyPolicy,
+        Verdict,
+    };
+
+    const EXAMPLE: &str = r#"7 6 4 2 1
+1 2 7 8 9
+9 7 6 2 1
+1 3 2 4 5
+8 6 4 4 1
+1 3 6 7 9"#;
+
+    #[test]
+    fn test_part1() {
+        assert_eq!(2, day2_part1(EXAMPLE.to_string()));
+    }
+
+    #[test]
+    fn test_part2() {
+        assert_eq!(4, day2_part2(EXAMPLE.to_string()));
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let input = "7 6 4 2 1\n\n1 3 6 7 9".to_string();
+        assert_eq!(2, day2_part1(input.clone()));
+        assert_eq!(2, day2_part2(input));
+
+        let report = Report { levels: vec![] };
+        assert_eq!(Verdict::Safe, report.check(&SafetyPolicy::default()));
+        assert_eq!(
+            Some(vec![]),
+            report.is_safe_with_tolerance(0, &SafetyPolicy::default())
+        );
+    }
+
+    #[test]
+    fn test_tolerance() {
+        let policy = SafetyPolicy::default();
+        assert_eq!(2, day2_with_tolerance(EXAMPLE.to_string(), 0, policy));
+        assert_eq!(4, day2_with_tolerance(EXAMPLE.to_string(), 1, policy));
+
+        let report = Report {
+            levels: vec![1, 9, 2, 3, 9, 4],
+        };
+        assert_eq!(None, report.is_safe_with_tolerance(1, &policy));
+        assert_eq!(Some(vec![1, 4]), report.is_safe_with_tolerance(2, &policy));
+
+        let report = Report {
+            levels: vec![20, 1, 2, 30, 40, 3, 4],
+        };
+        assert_eq!(None, report.is_safe_with_tolerance(2, &policy));
+        assert_eq!(
+            Some(vec![0, 3, 4]),
+            report.is_safe_with_tolerance(3, &policy)
+        );
+    }
+
+    #[test]
+    fn test_policy_bounds() {
+        assert_eq!(Ok(SafetyPolicy::default()), SafetyPolicy::new(1, 3));
+        assert_eq!(
+            Err("The minimum step 4 is larger than the maximum step 2".to_string()),
+            SafetyPolicy::new(4, 2)
+        );
+    }
+
+    #[test]
+    fn test_two_levels_with_dampener() {
+        let report = Report { levels: vec![1, 9] };
+        assert!(!report.is_safe(false));
+        assert!(report.is_safe(true));
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = SafetyPolicy::default();
+        let report = Report {
+            levels: vec![1, 3, 2, 4, 5],
+        };
+        assert_eq!(
+            Verdict::Unsafe {
+                index: 1,
+                levels: (3, 2),
+                rule: Rule::DirectionChange
+            },
+            report.check(&policy)
+        );
+
+        let report = Report {
+            levels: vec![8, 6, 4, 4, 1],
+        };
+        assert_eq!(
+            Verdict::Unsafe {
+                index: 2,
+                levels: (4, 4),
+                rule: Rule::Plateau
+            },
+            report.check(&policy)
+        );
+
+        let policy = SafetyPolicy {
+            allow_plateaus: true,
+            allow_direction_change: true,
+            ..SafetyPolicy::default()
+        };
+        assert_eq!(Verdict::Safe, report.check(&policy));
+        let report = Report {
+            levels: vec![1, 3, 2, 4],
+        };
+        assert_eq!(
+            Verdict::Unsafe {
+                index: 2,
+                levels: (2, 4),
+                rule: Rule::DirectionChange
+            },
+            report.check(&policy)
+        );
+
+        let policy = SafetyPolicy {
+            min_step: 2,
+            max_step: 4,
+            ..SafetyPolicy::default()
+        };
+        let report = Report {
+            levels: vec![1, 3, 7, 12],
+        };
+        assert_eq!(
+            Verdict::Unsafe {
+                index: 2,
+                levels: (7, 12),
+                rule: Rule::StepTooLarge
+            },
+            report.check(&policy)
+        );
+    }
+
+    #[test]
+    fn test_day2_check() {
+        assert_eq!(
+            "Report 2: 2 -> 7 at levels 2-3: step is too large
+Report 3: 6 -> 2 at levels 3-4: step is too large
+Report 4: 3 -> 2 at levels 2-3: levels must not change direction
+Report 5: 4 -> 4 at levels 3-4: levels must not stay the same
+2 of 6 reports are safe
+",
+            day2_check(EXAMPLE.to_string(), SafetyPolicy::default())
+        );
+    }
+
+    fn policy() -> impl Strategy<Value = SafetyPolicy> {
+        (0..3usize, 0..4usize, any::<bool>(), any::<bool>()).prop_map(
+            |(min_step, extra, allow_plateaus, allow_direction_change)| SafetyPolicy {
+                min_step,
+                max_step: min_step + extra,
+                allow_plateaus,
+                allow_direction_change,
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn safe_reports_stay_safe_with_dampener(levels in prop::collection::vec(1..20usize, 0..10)) {
+            let report = Report { levels };
+            prop_assert!(!report.is_safe(false) || report.is_safe(true));
+        }
+
+        #[test]
+        fn tolerance_matches_brute_force(
+            levels in prop::collection::vec(1..12usize, 0..9),
+            tolerance in 0..4usize,
+            policy in policy(),
+        ) {
+            let report = Report { levels: levels.clone() };
+            let brute_force = (0..=tolerance.min(levels.len().saturating_sub(1))).any(|count| {
+                (0..levels.len()).combinations(count).any(|removed| {
+                    let remaining: Vec<usize> = (0..levels.len())
+                        .filter(|i| !removed.contains(i))
+                        .map(|i| levels[i])
+                        .collect();
+                    Report { levels: remaining }.check(&policy) == Verdict::Safe
+                })
+            });
+
+            let removals = report.is_safe_with_tolerance(tolerance, &policy);
+            prop_assert_eq!(brute_force, removals.is_some());
+
+            if let Some(removals) = removals {
+                prop_assert!(removals.len() <= tolerance);
+                let remaining: Vec<usize> = (0..levels.len())
+                    .filter(|i| !removals.contains(i))
+                    .map(|i| levels[i])
+                    .collect();
+                let report = Report { levels: remaining };
+                prop_assert_eq!(Verdict::Safe, report.check(&policy));
+            }
+        }
+    }
+}
@@ -3,6 +3,8 @@ use std::{
     ops::Add,
 };
 
+use crate::{profile::phase, util::normalize_input};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Position {
     x: usize,
@@ -67,9 +69,10 @@ struct Cheat {
 
 impl Race {
     fn from_input(input: &str) -> Self {
-        let height = input.trim().lines().count();
+        let input = normalize_input(input);
+        let height = input.lines().count();
         let width = input.lines().nth(0).unwrap_or("").chars().count();
-        let map = input.trim().replace("\n", "");
+        let map = input.replace("\n", "");
 
         let start_idx = map.find("S").expect("There is no start");
         let end_idx = map.find("E").expect("There is no end");
@@ -93,7 +96,7 @@ impl Race {
             height,
         };
 
-        result.parse_path();
+        phase("Race::parse_path", || result.parse_path());
 
         result
     }
@@ -170,29 +173,31 @@ impl Race {
         {
             Direction::West
         } else {
-            self.print();
+            print!("{}", self.render());
             panic!("There is no way out: {:?}", position)
         }
     }
 
-    fn print(&self) {
+    fn render(&self) -> String {
+        let mut output = String::new();
         for y in 0..self.height {
             for x in 0..self.width {
                 let position = Position { x, y };
                 if self.start == position {
-                    print!("S");
+                    output.push('S');
                 } else if self.end == position {
-                    print!("E");
+                    output.push('E');
                 } else if self.position_steps.contains_key(&position) {
-                    print!("X");
+                    output.push('X');
                 } else if self.get_tile(position) == Tile::Wall {
-                    print!("#");
+                    output.push('#');
                 } else {
-                    print!(".")
+                    output.push('.')
                 }
             }
-            println!();
+            output.push('\n');
         }
+        output
     }
 
     fn get_cheats(&self, len: usize) -> Vec<Cheat> {
@@ -242,9 +247,9 @@ impl Race {
 }
 
 pub fn day20_part1(input: String) -> usize {
-    let race = Race::from_input(&input);
+    let race = phase("Race::from_input", || Race::from_input(&input));
 
-    let cheats = race.get_cheats(2);
+    let cheats = phase("Race::get_cheats", || race.get_cheats(2));
 
     cheats
         .iter()
@@ -253,9 +258,9 @@ pub fn day20_part1(input: String) -> usize {
 }
 
 pub fn day20_part2(input: String) -> usize {
-    let race = Race::from_input(&input);
+    let race = phase("Race::from_input", || Race::from_input(&input));
 
-    let cheats = race.get_cheats(20);
+    let cheats = phase("Race::get_cheats", || race.get_cheats(20));
 
     cheats
         .iter()
@@ -267,8 +272,33 @@ pub fn day20_part2(input: String) -> usize {
 mod test {
     use itertools::Itertools;
 
+    use crate::snapshot::assert_snapshot;
+
     use super::Race;
 
+    #[test]
+    fn test_render_path() {
+        let race = Race::from_input(
+            r#"###############
+#...#...#.....#
+#.#.#.#.#.###.#
+#S#...#.#.#...#
+#######.#.#.###
+#######.#.#...#
+#######.#.###.#
+###..E#...#...#
+###.#######.###
+#...###...#...#
+#.#####.#.###.#
+#.#...#.#.#...#
+#.#.#.#.#.#.###
+#...#...#...###
+###############"#,
+        );
+
+        assert_snapshot("day20_path", &race.render());
+    }
+
     #[test]
     fn test_part1() {
         let race = Race::from_input(
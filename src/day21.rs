@@ -1,5 +1,7 @@
 use std::{collections::HashMap, ops::Add, vec};
 
+use crate::util::normalize_input;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 struct Position {
     x: isize,
@@ -251,10 +253,8 @@ impl KeypadChain {
 }
 
 pub fn day21_part1(input: String) -> usize {
-    let mut code_entries = input
-        .trim()
-        .lines()
-        .map(|line| CodeEntry::from_input(line, 2));
+    let input = normalize_input(&input);
+    let mut code_entries = input.lines().map(|line| CodeEntry::from_input(line, 2));
 
     let mut sum = 0;
     for mut code_entry in &mut code_entries {
@@ -265,10 +265,8 @@ pub fn day21_part1(input: String) -> usize {
 }
 
 pub fn day21_part2(input: String) -> usize {
-    let mut code_entries = input
-        .trim()
-        .lines()
-        .map(|line| CodeEntry::from_input(line, 25));
+    let input = normalize_input(&input);
+    let mut code_entries = input.lines().map(|line| CodeEntry::from_input(line, 25));
 
     let mut sum = 0;
     for mut code_entry in &mut code_entries {
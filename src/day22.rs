@@ -2,6 +2,8 @@ use std::{sync::Arc, thread};
 
 use itertools::Itertools;
 
+use crate::{profile::phase, util::normalize_input};
+
 #[derive(Clone)]
 struct Market {
     buyer_seeds: Vec<usize>,
@@ -12,7 +14,10 @@ struct Market {
 
 impl Market {
     fn from_input(input: &str) -> Self {
-        let buyer_seeds: Vec<usize> = input.trim().lines().map(|l| l.parse().unwrap()).collect();
+        let buyer_seeds: Vec<usize> = normalize_input(input)
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
         let count = buyer_seeds.len();
 
         Self {
@@ -119,16 +124,18 @@ fn prune(n: usize) -> usize {
 }
 
 pub fn day22_part1(input: String) -> usize {
-    let mut market = Market::from_input(&input);
+    let mut market = phase("Market::from_input", || Market::from_input(&input));
 
-    market.get_seeds_after(2000).iter().sum()
+    phase("Market::get_seeds_after", || market.get_seeds_after(2000))
+        .iter()
+        .sum()
 }
 
 pub fn day22_part2(input: String) -> usize {
-    let mut market = Market::from_input(&input);
+    let mut market = phase("Market::from_input", || Market::from_input(&input));
 
-    market.get_seeds_after(2000);
-    market.get_best_prefix()
+    phase("Market::get_seeds_after", || market.get_seeds_after(2000));
+    phase("Market::get_best_prefix", || market.get_best_prefix())
 }
 
 #[cfg(test)]
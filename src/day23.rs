@@ -6,6 +6,8 @@ use std::{
 
 use itertools::Itertools;
 
+use crate::util::normalize_input;
+
 type Computer = String;
 
 struct LanParty {
@@ -50,8 +52,8 @@ impl FromIterator<String> for ComputerGroup {
 impl LanParty {
     fn from_input(input: &str) -> Self {
         let mut connections: HashMap<Computer, HashSet<Computer>> = HashMap::new();
+        let input = normalize_input(input);
         let connection_tuples: Vec<(&str, &str)> = input
-            .trim()
             .lines()
             .flat_map(|line| line.split("-").collect_tuple())
             .collect();
@@ -2,11 +2,19 @@ use std::{
     cell::RefCell,
     cmp::Reverse,
     collections::{HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
 };
 
 use itertools::Itertools;
 use log::warn;
 
+use crate::{
+    profile::phase,
+    repl::{parse_arg, Explore, ReplError},
+    util::{normalize_input, parse_sections},
+};
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 enum GateType {
     And,
@@ -50,6 +58,16 @@ impl Gate {
     }
 }
 
+/// Wires whose values depend on themselves, the first wire repeated at the end
+#[derive(Debug, PartialEq, Eq)]
+struct WireCycle(Vec<String>);
+
+impl Display for WireCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Wire cycle {}", self.0.join(" -> "))
+    }
+}
+
 #[derive(Clone)]
 struct WireNetwork {
     wire_states: RefCell<HashMap<String, bool>>,
@@ -58,20 +76,48 @@ struct WireNetwork {
     swap_ideas: Vec<(String, String)>,
 }
 
+/// The initial `name: 0|1` wire states
+struct WireStates(HashMap<String, bool>);
+
+impl FromStr for WireStates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .map(|line| match line.split_once(": ") {
+                Some((name, "0")) => Ok((name.to_string(), false)),
+                Some((name, "1")) => Ok((name.to_string(), true)),
+                _ => Err(format!("Expected a wire like x00: 1, found {line:?}")),
+            })
+            .collect::<Result<_, _>>()
+            .map(WireStates)
+    }
+}
+
+/// The `a OP b -> output` gates by their output wire
+struct Gates(HashMap<String, Gate>);
+
+impl FromStr for Gates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .map(|line| match line.split_once(" -> ") {
+                Some((config, output_wire)) => Ok((output_wire.to_string(), Gate::parse(config))),
+                None => Err(format!(
+                    "Expected a gate like x00 AND y00 -> z00, found {line:?}"
+                )),
+            })
+            .collect::<Result<_, _>>()
+            .map(Gates)
+    }
+}
+
 impl WireNetwork {
     fn from_input(input: &str) -> Self {
-        let sections: Vec<_> = input.trim().split("\n\n").collect();
-        let wires = sections[0]
-            .lines()
-            .flat_map(|line| line.split(": ").collect_tuple::<(_, _)>());
-        let wire_states =
-            HashMap::from_iter(wires.map(|(name, state)| (name.to_string(), state == "1")));
-
-        let gates = sections[1]
-            .lines()
-            .flat_map(|line| line.split(" -> ").collect_tuple::<(_, _)>())
-            .map(|(config, output_wire)| (output_wire.to_string(), Gate::parse(config)));
-        let gates = HashMap::from_iter(gates);
+        let input = normalize_input(input);
+        let (WireStates(wire_states), Gates(gates)) =
+            parse_sections(&input).unwrap_or_else(|e| panic!("{e}"));
 
         Self {
             wire_states: RefCell::new(wire_states),
@@ -81,19 +127,19 @@ impl WireNetwork {
         }
     }
 
-    fn evaluate(&self, area: &str) -> usize {
+    fn evaluate(&self, area: &str) -> Result<usize, WireCycle> {
         let mut result_bits: Vec<_> = self
             .gates
-            .iter()
-            .filter(|(gate, _)| gate.starts_with(area))
-            .map(|(key, gate)| (key.clone(), self.evaluate_gate(gate)))
-            .collect();
+            .keys()
+            .filter(|gate| gate.starts_with(area))
+            .map(|key| Ok((key.clone(), self.evaluate_wire(key, &mut Vec::new())?)))
+            .collect::<Result<_, _>>()?;
 
         result_bits.extend(
             self.wire_states
                 .borrow()
                 .iter()
-                .filter(|(wire, _)| wire.starts_with(area))
+                .filter(|(wire, _)| wire.starts_with(area) && !self.gates.contains_key(*wire))
                 .map(|(wire, state)| (wire.clone(), *state)),
         );
         result_bits.sort_unstable_by_key(|(gate, _)| Reverse(gate.to_string()));
@@ -105,32 +151,44 @@ impl WireNetwork {
                 result += 1;
             }
         }
-        result
-    }
-
-    fn evaluate_gate(&self, gate: &Gate) -> bool {
-        let w1 = self.evaluate_wire(&gate.wire1);
-        let w2 = self.evaluate_wire(&gate.wire2);
-
-        match gate.gate_type {
-            GateType::And => w1 && w2,
-            GateType::Or => w1 || w2,
-            GateType::Xor => w1 ^ w2,
-        }
+        Ok(result)
     }
 
-    fn evaluate_wire(&self, wire: &str) -> bool {
+    /// Evaluate a wire. `path` holds the wires currently being evaluated, to detect
+    /// wires depending on themselves.
+    fn evaluate_wire(&self, wire: &str, path: &mut Vec<String>) -> Result<bool, WireCycle> {
         if let Some(state) = self.wire_states.borrow().get(wire) {
-            return *state;
+            return Ok(*state);
+        }
+        if let Some(start) = path.iter().position(|other| other == wire) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(wire.to_string());
+            return Err(WireCycle(cycle));
         }
 
         let gate = self.gates.get(wire).unwrap();
-        let result = self.evaluate_gate(gate);
+        path.push(wire.to_string());
+        let w1 = self.evaluate_wire(&gate.wire1, path)?;
+        let w2 = self.evaluate_wire(&gate.wire2, path)?;
+        path.pop();
+
+        let result = match gate.gate_type {
+            GateType::And => w1 && w2,
+            GateType::Or => w1 || w2,
+            GateType::Xor => w1 ^ w2,
+        };
         self.wire_states
             .borrow_mut()
             .insert(wire.to_string(), result);
 
-        result
+        Ok(result)
+    }
+
+    /// Forget all computed gate outputs, keeping only the initial wire states.
+    fn reset_evaluation(&self) {
+        self.wire_states
+            .borrow_mut()
+            .retain(|wire, _| !self.gates.contains_key(wire));
     }
 
     fn swap(&mut self, gate_a: &str, gate_b: &str) {
@@ -260,14 +318,58 @@ impl WireNetwork {
     }
 }
 
+impl Explore for WireNetwork {
+    fn commands(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("eval <prefix>", "Evaluate all wires starting with prefix"),
+            ("swap <a> <b>", "Swap the outputs of two gates"),
+            ("swaps", "List the swapped gate outputs"),
+        ]
+    }
+
+    fn execute(&mut self, command: &str, args: &[&str]) -> Result<String, ReplError> {
+        match command {
+            "eval" => {
+                let prefix: String = parse_arg(args, 0, "prefix")?;
+                self.evaluate(&prefix)
+                    .map(|result| result.to_string())
+                    .map_err(|cycle| ReplError::Failed(cycle.to_string()))
+            }
+            "swap" => {
+                let gate_a: String = parse_arg(args, 0, "a")?;
+                let gate_b: String = parse_arg(args, 1, "b")?;
+                for gate in [&gate_a, &gate_b] {
+                    if !self.gates.contains_key(gate) {
+                        return Err(ReplError::InvalidArgument(gate.clone()));
+                    }
+                }
+                self.swap(&gate_a, &gate_b);
+                self.reset_evaluation();
+                Ok(String::new())
+            }
+            "swaps" => Ok(self.swaps.iter().join(",")),
+            _ => Err(ReplError::UnknownCommand(command.to_string())),
+        }
+    }
+}
+
+pub fn day24_repl(input: String) -> Box<dyn Explore> {
+    Box::new(WireNetwork::from_input(&input))
+}
+
 pub fn day24_part1(input: String) -> usize {
-    let wire_network = WireNetwork::from_input(&input);
+    let wire_network = phase("WireNetwork::from_input", || {
+        WireNetwork::from_input(&input)
+    });
 
-    wire_network.evaluate("z")
+    phase("WireNetwork::evaluate", || wire_network.evaluate("z"))
+        .unwrap_or_else(|cycle| panic!("{cycle}"))
 }
 
 pub fn day24_part2(input: String) -> String {
-    let mut wire_network = WireNetwork::from_input(&input);
+    let mut wire_network = phase("WireNetwork::from_input", || {
+        WireNetwork::from_input(&input)
+    });
 
     for i in 0..45 {
         if let Some(sum_gate) = wire_network.get_sum_gate(i) {
@@ -317,7 +419,33 @@ fn fix_gates_with_swaps(
 
 #[cfg(test)]
 mod test {
-    use crate::day24::day24_part1;
+    use crate::{
+        day24::{day24_part1, day24_repl},
+        repl::ReplError,
+    };
+
+    #[test]
+    fn test_swap_into_cycle() {
+        let mut network = day24_repl(
+            r#"x00: 1
+y00: 0
+
+x00 XOR y00 -> abc
+abc OR y00 -> def
+def AND x00 -> z00
+def XOR y00 -> z01"#
+                .to_string(),
+        );
+
+        assert_eq!(Ok("3".to_string()), network.execute("eval", &["z"]));
+        assert_eq!(Ok(String::new()), network.execute("swap", &["abc", "z00"]));
+        let Err(ReplError::Failed(reason)) = network.execute("eval", &["z"]) else {
+            panic!("Expected the cycle to be reported");
+        };
+        assert!(reason.starts_with("Wire cycle "), "{reason}");
+        assert_eq!(Ok(String::new()), network.execute("swap", &["abc", "z00"]));
+        assert_eq!(Ok("3".to_string()), network.execute("eval", &["z"]));
+    }
 
     #[test]
     fn test_part1() {
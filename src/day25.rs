@@ -1,3 +1,8 @@
+use crate::{
+    profile::phase,
+    util::{normalize_input, sections},
+};
+
 type KeyOrLock = Vec<u8>;
 
 #[derive(Debug)]
@@ -8,7 +13,8 @@ struct Locksmith {
 
 impl Locksmith {
     fn from_input(input: &str) -> Self {
-        let items = input.split("\n\n");
+        let input = normalize_input(input);
+        let items = sections(&input);
         let mut locks: Vec<Vec<u8>> = Vec::new();
         let mut keys: Vec<Vec<u8>> = Vec::new();
 
@@ -66,13 +72,14 @@ impl Locksmith {
 }
 
 pub fn day25_part1(input: String) -> usize {
-    let locksmith = Locksmith::from_input(&input);
-
-    locksmith
-        .find_possible_combinations()
-        .iter()
-        .map(|(_lock, keys)| keys.len())
-        .sum()
+    let locksmith = phase("Locksmith::from_input", || Locksmith::from_input(&input));
+
+    phase("Locksmith::find_possible_combinations", || {
+        locksmith.find_possible_combinations()
+    })
+    .iter()
+    .map(|(_lock, keys)| keys.len())
+    .sum()
 }
 
 pub fn day25_part2(_input: String) -> usize {
@@ -1,65 +1,422 @@
-use regex::Regex;
-
-pub fn day3_part1(input: String) -> usize {
-    let re = Regex::new(r"mul\((\d+),(\d+)\)").unwrap();
-    let mut sum = 0;
-    for mul in re.captures_iter(&input) {
-        let (_, [number1, number2]) = mul.extract();
-        sum += number1.parse::<usize>().unwrap() * number2.parse::<usize>().unwrap();
-    }
-
-    sum
-}
-pub fn day3_part2(input: String) -> usize {
-    let dos: Vec<usize> = input.match_indices("do()").map(|(pos, _)| pos).collect();
-    let donts: Vec<usize> = input.match_indices("don't()").map(|(pos, _)| pos).collect();
-
-    let mut disabled_ranges = Vec::<(usize, usize)>::new();
-    let mut last_do_index = 0;
-    for dont_position in donts {
-        let mut dont_end: Option<usize> = None;
-        for (i, doit) in dos.iter().enumerate().skip(last_do_index) {
-            if *doit > dont_position {
-                last_do_index = i;
-                dont_end = Some(*doit);
-                break;
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use crate::profile::phase;
+
+/// What an instruction does when it is executed.
+#[derive(Debug, Clone, Copy)]
+enum Semantics {
+    /// Computes a value from the arguments which is added to the sum while enabled.
+    /// `None` marks an overflow, which is reported as an error.
+    Value(fn(&[i64]) -> Option<i64>),
+    /// Enables or disables the value instructions when conditionals are honored
+    Enable(bool),
+}
+
+fn mul(arguments: &[i64]) -> Option<i64> {
+    arguments[0].checked_mul(arguments[1])
+}
+
+fn add(arguments: &[i64]) -> Option<i64> {
+    arguments[0].checked_add(arguments[1])
+}
+
+fn neg(arguments: &[i64]) -> Option<i64> {
+    arguments[0].checked_neg()
+}
+
+/// Operations an instruction can be bound to, with their arity.
+const OPERATIONS: &[(&str, usize, Semantics)] = &[
+    ("mul", 2, Semantics::Value(mul)),
+    ("add", 2, Semantics::Value(add)),
+    ("neg", 1, Semantics::Value(neg)),
+    ("enable", 0, Semantics::Enable(true)),
+    ("disable", 0, Semantics::Enable(false)),
+];
+
+/// The dialect of the puzzle.
+pub const DEFAULT_DIALECT: &str = "mul,do=enable,don't=disable";
+/// The puzzle dialect extended by `add(a,b)` and `neg(a)`.
+pub const EXTENDED_DIALECT: &str = "mul,do=enable,don't=disable,add,neg";
+
+#[derive(Debug, Clone)]
+struct Definition {
+    name: String,
+    arity: usize,
+    /// Allowed number of digits of literal arguments
+    min_digits: usize,
+    max_digits: usize,
+    semantics: Semantics,
+}
+
+impl FromStr for Definition {
+    type Err = String;
+
+    /// Parse `name[=operation][:min-max]`, e.g. `do=enable` or `mul:1-3`. The operation
+    /// defaults to the name and literal arguments to 1 to 3 digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (binding, digits) = s.split_once(':').unwrap_or((s, "1-3"));
+        let (name, operation) = binding.split_once('=').unwrap_or((binding, binding));
+        if name.is_empty() || name.contains(['(', ')', ',']) {
+            return Err(format!("Invalid instruction name {name:?}"));
+        }
+
+        let (_, arity, semantics) = OPERATIONS
+            .iter()
+            .find(|(known, _, _)| *known == operation)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown operation {operation:?}, expected one of {}",
+                    OPERATIONS
+                        .iter()
+                        .map(|(known, _, _)| *known)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+        let (min_digits, max_digits) = digits
+            .split_once('-')
+            .and_then(|(min, max)| Some((min.parse().ok()?, max.parse().ok()?)))
+            .filter(|(min, max)| 0 < *min && min <= max)
+            .ok_or_else(|| format!("Invalid digit limits {digits:?}, expected e.g. 1-3"))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            arity: *arity,
+            min_digits,
+            max_digits,
+            semantics: *semantics,
+        })
+    }
+}
+
+/// The instructions a dialect of the corrupted memory language understands.
+#[derive(Debug, Clone)]
+pub struct InstructionSet {
+    definitions: Vec<Definition>,
+    /// How deep value instructions may be nested as arguments of other instructions
+    max_depth: usize,
+}
+
+impl Default for InstructionSet {
+    fn default() -> Self {
+        DEFAULT_DIALECT.parse().unwrap()
+    }
+}
+
+impl FromStr for InstructionSet {
+    type Err = String;
+
+    /// Parse comma separated instruction definitions, see `Definition::from_str`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let definitions = s
+            .split(',')
+            .map(|definition| definition.trim().parse())
+            .collect::<Result<Vec<Definition>, _>>()?;
+
+        for (i, definition) in definitions.iter().enumerate() {
+            if definitions[..i]
+                .iter()
+                .any(|other| other.name == definition.name)
+            {
+                return Err(format!("Duplicate instruction {:?}", definition.name));
+            }
+        }
+
+        Ok(Self {
+            definitions,
+            max_depth: 0,
+        })
+    }
+}
+
+pub fn parse_instruction_set(value: &str) -> Result<InstructionSet, String> {
+    value.parse()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InterpretError {
+    UnknownInstruction(String),
+    Overflow { offset: usize },
+}
+
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpretError::UnknownInstruction(name) => write!(f, "Unknown instruction {name}"),
+            InterpretError::Overflow { offset } => {
+                write!(f, "The instruction at byte {offset} overflows")
+            }
+        }
+    }
+}
+
+impl InstructionSet {
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    fn definition(&self, name: &str) -> Result<&Definition, InterpretError> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.name == name)
+            .ok_or_else(|| InterpretError::UnknownInstruction(name.to_string()))
+    }
+
+    /// Parse the call at the start of `input`, returning it and its length in bytes.
+    /// `depth` is the number of calls this one is nested in.
+    fn parse_call(&self, input: &str, depth: usize) -> Option<(Call, usize)> {
+        self.definitions.iter().find_map(|definition| {
+            let rest = input.strip_prefix(&definition.name)?.strip_prefix('(')?;
+            let (arguments, rest) = self.parse_arguments(definition, rest, depth)?;
+            let call = Call {
+                name: definition.name.clone(),
+                arguments,
+            };
+            Some((call, input.len() - rest.len()))
+        })
+    }
+
+    fn parse_arguments<'a>(
+        &self,
+        definition: &Definition,
+        mut input: &'a str,
+        depth: usize,
+    ) -> Option<(Vec<Argument>, &'a str)> {
+        let mut arguments = Vec::with_capacity(definition.arity);
+        for i in 0..definition.arity {
+            if i > 0 {
+                input = input.strip_prefix(',')?;
+            }
+            let (argument, rest) = self.parse_argument(definition, input, depth)?;
+            arguments.push(argument);
+            input = rest;
+        }
+
+        Some((arguments, input.strip_prefix(')')?))
+    }
+
+    fn parse_argument<'a>(
+        &self,
+        definition: &Definition,
+        input: &'a str,
+        depth: usize,
+    ) -> Option<(Argument, &'a str)> {
+        let digits = input.bytes().take_while(u8::is_ascii_digit).count();
+        if digits > 0 {
+            if !(definition.min_digits..=definition.max_digits).contains(&digits) {
+                return None;
+            }
+            let number = input[..digits].parse().ok()?;
+            return Some((Argument::Literal(number), &input[digits..]));
+        }
+
+        if depth >= self.max_depth {
+            return None;
+        }
+        let (call, len) = self.parse_call(input, depth + 1)?;
+        match self.definition(&call.name).ok()?.semantics {
+            Semantics::Value(_) => Some((Argument::Call(call), &input[len..])),
+            Semantics::Enable(_) => None,
+        }
+    }
+
+    /// Evaluate a value call found at `offset`. Only value calls are parsed as arguments.
+    fn evaluate(&self, offset: usize, call: &Call) -> Result<i64, InterpretError> {
+        let mut arguments = Vec::with_capacity(call.arguments.len());
+        for argument in &call.arguments {
+            match argument {
+                Argument::Literal(number) => arguments.push(*number),
+                Argument::Call(call) => arguments.push(self.evaluate(offset, call)?),
+            }
+        }
+
+        match self.definition(&call.name)?.semantics {
+            Semantics::Value(function) => {
+                function(&arguments).ok_or(InterpretError::Overflow { offset })
+            }
+            Semantics::Enable(_) => unreachable!("{} is not a value instruction", call.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Call {
+    name: String,
+    arguments: Vec<Argument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Argument {
+    Literal(i64),
+    Call(Call),
+}
+
+impl Display for Call {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (i, argument) in self.arguments.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match argument {
+                Argument::Literal(number) => write!(f, "{number}")?,
+                Argument::Call(call) => write!(f, "{call}")?,
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+/// Finds the instructions in corrupted memory together with their byte offset.
+struct Lexer<'a> {
+    instructions: &'a InstructionSet,
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(instructions: &'a InstructionSet, input: &'a str) -> Self {
+        Self {
+            instructions,
+            input,
+            position: 0,
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = (usize, Call);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(current) = self.input[self.position..].chars().next() {
+            let offset = self.position;
+            if let Some((call, len)) = self.instructions.parse_call(&self.input[offset..], 0) {
+                self.position += len;
+                return Some((offset, call));
             }
+            self.position += current.len_utf8();
         }
 
-        disabled_ranges.push(match dont_end {
-            Some(end) => (dont_position, end),
-            None => (dont_position, input.len()),
-        });
-    }
-
-    let re = Regex::new(r"mul\((\d+),(\d+)\)").unwrap();
-    let mut sum = 0;
-
-    let input_str = input.as_str();
-    for mul in re.captures_iter(input_str) {
-        let (complete_mul, [number1, number2]) = mul.extract();
-        let offset = complete_mul.as_ptr() as usize - input_str.as_ptr() as usize;
-        if disabled_ranges
-            .clone()
-            .into_iter()
-            .any(|(start, end)| start < offset && end > offset)
-        {
-            continue;
+        None
+    }
+}
+
+struct Interpreter<'a> {
+    instructions: &'a InstructionSet,
+    /// Whether `do()` and `don't()` toggle the execution of value instructions
+    conditionals: bool,
+    enabled: bool,
+    sum: i64,
+    /// Executed instructions with their byte offset in the input
+    executed: Vec<(usize, Call)>,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(instructions: &'a InstructionSet, conditionals: bool) -> Self {
+        Self {
+            instructions,
+            conditionals,
+            enabled: true,
+            sum: 0,
+            executed: Vec::new(),
         }
-        sum += number1.parse::<usize>().unwrap() * number2.parse::<usize>().unwrap();
     }
 
-    sum
+    fn run(&mut self, calls: impl Iterator<Item = (usize, Call)>) -> Result<(), InterpretError> {
+        for (offset, call) in calls {
+            if self.execute(offset, &call)? {
+                self.executed.push((offset, call));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Execute a single instruction, returning whether it had any effect.
+    fn execute(&mut self, offset: usize, call: &Call) -> Result<bool, InterpretError> {
+        match self.instructions.definition(&call.name)?.semantics {
+            Semantics::Value(_) if self.enabled => {
+                let value = self.instructions.evaluate(offset, call)?;
+                self.sum = self
+                    .sum
+                    .checked_add(value)
+                    .ok_or(InterpretError::Overflow { offset })?;
+                Ok(true)
+            }
+            Semantics::Value(_) => Ok(false),
+            Semantics::Enable(_) if !self.conditionals => Ok(false),
+            Semantics::Enable(enabled) => {
+                self.enabled = enabled;
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// Run the instructions found in `input`. The raw input is lexed without normalizing
+/// it, so the recorded offsets point into the input as given; no instruction can
+/// contain a byte order mark or a line ending anyway.
+fn interpret<'a>(
+    instructions: &'a InstructionSet,
+    input: String,
+    conditionals: bool,
+) -> Result<Interpreter<'a>, InterpretError> {
+    let mut interpreter = Interpreter::new(instructions, conditionals);
+    phase("Interpreter::run", || {
+        interpreter.run(Lexer::new(instructions, &input))
+    })?;
+
+    Ok(interpreter)
+}
+
+pub fn day3_part1(input: String) -> Result<i64, InterpretError> {
+    Ok(interpret(&InstructionSet::default(), input, false)?.sum)
+}
+
+pub fn day3_part2(input: String) -> Result<i64, InterpretError> {
+    Ok(interpret(&InstructionSet::default(), input, true)?.sum)
+}
+
+pub fn day3_with_instructions(
+    input: String,
+    instructions: &InstructionSet,
+) -> Result<i64, InterpretError> {
+    Ok(interpret(instructions, input, true)?.sum)
+}
+
+/// The executed instructions with their byte offset in the input, followed by the sum.
+pub fn day3_trace(input: String, instructions: &InstructionSet) -> Result<String, InterpretError> {
+    let interpreter = interpret(instructions, input, true)?;
+
+    let mut output = String::new();
+    for (offset, call) in &interpreter.executed {
+        writeln!(output, "byte {offset}: {call}").unwrap();
+    }
+    writeln!(output, "Sum: {}", interpreter.sum).unwrap();
+
+    Ok(output)
 }
 
 #[cfg(test)]
 mod test {
-    use crate::day3::{day3_part1, day3_part2};
+    use crate::day3::{
+        day3_part1, day3_part2, day3_trace, day3_with_instructions, interpret, Argument, Call,
+        InstructionSet, InterpretError, EXTENDED_DIALECT,
+    };
+
+    fn day3_extended(input: String) -> Result<i64, InterpretError> {
+        let instructions = EXTENDED_DIALECT.parse::<InstructionSet>().unwrap();
+        day3_with_instructions(input, &instructions.with_max_depth(16))
+    }
 
     #[test]
     fn part1_test_input() {
         assert_eq!(
-            161,
+            Ok(161),
             day3_part1(
                 "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))"
                     .to_string()
@@ -70,11 +427,159 @@ mod test {
     #[test]
     fn part2_test_input() {
         assert_eq!(
-            48,
+            Ok(48),
             day3_part2(
                 "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))"
                     .to_string()
             )
         )
     }
+
+    #[test]
+    fn test_executed_instructions() {
+        let instructions = InstructionSet::default();
+        let interpreter = interpret(
+            &instructions,
+            "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))".to_string(),
+            true,
+        )
+        .unwrap();
+        let call = |name: &str, arguments: &[i64]| Call {
+            name: name.to_string(),
+            arguments: arguments.iter().copied().map(Argument::Literal).collect(),
+        };
+        assert_eq!(
+            vec![
+                (1, call("mul", &[2, 4])),
+                (20, call("don't", &[])),
+                (59, call("do", &[])),
+                (64, call("mul", &[8, 5])),
+            ],
+            interpreter.executed
+        );
+    }
+
+    #[test]
+    fn test_offsets_into_raw_input() {
+        let instructions = InstructionSet::default();
+        let interpreter = interpret(
+            &instructions,
+            "\u{feff}  mul(2,3)\r\nmul(4,5)".to_string(),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![5, 15],
+            interpreter
+                .executed
+                .iter()
+                .map(|(offset, _)| *offset)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(26, interpreter.sum);
+    }
+
+    #[test]
+    fn test_trace() {
+        let instructions = EXTENDED_DIALECT
+            .parse::<InstructionSet>()
+            .unwrap()
+            .with_max_depth(16);
+        assert_eq!(
+            "byte 2: mul(2,neg(3))\nbyte 15: don't()\nbyte 30: do()\nbyte 34: add(1,1)\nSum: -4\n",
+            day3_trace(
+                "\r\nmul(2,neg(3))don't()add(4,4)do()add(1,1)".to_string(),
+                &instructions
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_digit_limit() {
+        assert_eq!(Ok(6), day3_part1("mul(1234,5)mul(2,3)mul(,1)".to_string()));
+    }
+
+    #[test]
+    fn test_extended() {
+        assert_eq!(
+            Ok(-10 + 7 + 4),
+            day3_extended("neg(mul(2,5))add(3,4)mul(add(1,1),2)add(do(),1)".to_string())
+        );
+        assert_eq!(
+            Ok(3),
+            day3_extended("add(1,2)don't()neg(4)do()mul(neg(1),0)".to_string())
+        );
+        assert_eq!(Ok(0), day3_part1("add(1,2)mul(neg(1),2)".to_string()));
+    }
+
+    #[test]
+    fn test_configured_dialect() {
+        let instructions = "times=mul:1-4,on=enable,off=disable,minus=neg"
+            .parse::<InstructionSet>()
+            .unwrap()
+            .with_max_depth(1);
+        assert_eq!(
+            Ok(1234 * 2 - 3 + 1),
+            day3_with_instructions(
+                "times(1234,2)off()times(5,5)on()minus(3)mul(2,2)minus(minus(1))".to_string(),
+                &instructions
+            )
+        );
+
+        assert!("mul,pow".parse::<InstructionSet>().is_err());
+        assert!("mul:3-1".parse::<InstructionSet>().is_err());
+        assert!("mul,mul=add".parse::<InstructionSet>().is_err());
+        assert!("m(ul".parse::<InstructionSet>().is_err());
+    }
+
+    #[test]
+    fn test_sum_overflow() {
+        let instructions = "mul:1-19".parse::<InstructionSet>().unwrap();
+        assert_eq!(
+            Err(InterpretError::Overflow { offset: 28 }),
+            day3_with_instructions(
+                "mul(9223372036854775807,1)  mul(1,1)".to_string(),
+                &instructions
+            )
+        );
+    }
+
+    #[test]
+    fn test_call_overflow() {
+        let instructions = "mul:1-19,neg".parse::<InstructionSet>().unwrap();
+        assert_eq!(
+            Err(InterpretError::Overflow { offset: 9 }),
+            day3_with_instructions(
+                "mul(2,3) mul(9223372036854775807,2)".to_string(),
+                &instructions
+            )
+        );
+
+        let instructions = instructions.with_max_depth(1);
+        assert_eq!(
+            Err(InterpretError::Overflow { offset: 1 }),
+            day3_with_instructions(
+                "xneg(mul(9223372036854775807,2))".to_string(),
+                &instructions
+            )
+        );
+        assert_eq!(
+            "The instruction at byte 1 overflows",
+            InterpretError::Overflow { offset: 1 }.to_string()
+        );
+    }
+
+    #[test]
+    fn test_nesting_depth() {
+        let nested = |depth| "neg(".repeat(depth) + "1" + &")".repeat(depth);
+        let instructions = "neg".parse::<InstructionSet>().unwrap().with_max_depth(2);
+
+        let sum = |input: String| day3_with_instructions(input, &instructions).unwrap();
+        assert_eq!(-1, sum(nested(3)));
+        // Too deep to parse as a whole, the innermost calls that fit are run instead
+        assert_eq!(-1, sum(nested(4)));
+        assert_eq!(-1, sum(nested(100_000)));
+    }
 }
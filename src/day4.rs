@@ -1,124 +1,372 @@
+use std::{collections::HashMap, fmt::Write, str::FromStr};
+
+use clap::ValueEnum;
+
+use crate::{profile::phase, util::normalize_input};
+
 struct Grid {
     values: Vec<Vec<char>>,
     width: usize,
     height: usize,
 }
 
-pub fn day4_part1(input: String) -> usize {
-    let grid = parse_char_grid(input);
-    let mut sum = 0;
-
-    for line_index in 0..grid.values.len() {
-        let line = &grid.values[line_index];
-        for (char_index, c) in line.iter().enumerate() {
-            if *c == 'X' {
-                sum += count_xmas(&grid, line_index, char_index);
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Direction {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction {
+    const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::NorthEast,
+        Direction::East,
+        Direction::SouthEast,
+        Direction::South,
+        Direction::SouthWest,
+        Direction::West,
+        Direction::NorthWest,
+    ];
+
+    /// Step as (x, y) with y growing downwards.
+    fn offset(&self) -> (isize, isize) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::NorthEast => (1, -1),
+            Direction::East => (1, 0),
+            Direction::SouthEast => (1, 1),
+            Direction::South => (0, 1),
+            Direction::SouthWest => (-1, 1),
+            Direction::West => (-1, 0),
+            Direction::NorthWest => (-1, -1),
         }
     }
 
-    sum
+    fn opposite(&self) -> Direction {
+        Direction::ALL[(*self as usize + 4) % 8]
+    }
+}
+
+#[derive(Default)]
+struct TrieNode {
+    children: HashMap<char, usize>,
+    /// Indices of the words ending at this node
+    words: Vec<usize>,
 }
 
-pub fn day4_part2(input: String) -> usize {
-    let grid = parse_char_grid(input);
-    let mut sum = 0;
-
-    for line_index in 0..grid.values.len() {
-        let line = &grid.values[line_index];
-        for (char_index, c) in line.iter().enumerate() {
-            if *c == 'A' {
-                sum += count_x_mas(&grid, line_index, char_index);
+/// Prefix tree over all words so each walk through the grid checks every word at once.
+struct Trie {
+    nodes: Vec<TrieNode>,
+}
+
+impl Trie {
+    fn new(words: &[String]) -> Self {
+        let mut trie = Trie {
+            nodes: vec![TrieNode::default()],
+        };
+        for (index, word) in words.iter().enumerate() {
+            let mut node = 0;
+            for c in word.chars() {
+                node = match trie.nodes[node].children.get(&c) {
+                    Some(child) => *child,
+                    None => {
+                        trie.nodes.push(TrieNode::default());
+                        let child = trie.nodes.len() - 1;
+                        trie.nodes[node].children.insert(c, child);
+                        child
+                    }
+                };
             }
+            trie.nodes[node].words.push(index);
         }
+
+        trie
     }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct WordMatch<'a> {
+    /// Position (x, y) of the first letter
+    start: (usize, usize),
+    direction: Direction,
+    word: &'a str,
+}
 
-    sum
+struct WordSearch {
+    words: Vec<String>,
+    trie: Trie,
+    directions: Vec<Direction>,
+    /// Continue words on the opposite edge of the grid
+    wrap_around: bool,
 }
 
-fn count_x_mas(grid: &Grid, line_index: usize, char_index: usize) -> usize {
-    if line_index == 0
-        || char_index == 0
-        || line_index == grid.height - 1
-        || char_index == grid.width - 1
-    {
-        return 0;
+impl WordSearch {
+    fn new(words: &[&str]) -> Self {
+        let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+        Self {
+            trie: Trie::new(&words),
+            words,
+            directions: Direction::ALL.to_vec(),
+            wrap_around: false,
+        }
     }
-    let values = &grid.values;
 
-    let mas1 = String::from_iter([
-        values[line_index - 1][char_index - 1],
-        values[line_index][char_index],
-        values[line_index + 1][char_index + 1],
-    ]);
-    let mas2 = String::from_iter([
-        values[line_index + 1][char_index - 1],
-        values[line_index][char_index],
-        values[line_index - 1][char_index + 1],
-    ]);
+    /// Only search in the given directions.
+    fn directions(mut self, directions: &[Direction]) -> Self {
+        self.directions = directions.to_vec();
+        self
+    }
 
-    if (mas1 == "MAS" || mas1 == "SAM") && (mas2 == "MAS" || mas2 == "SAM") {
-        println!("Found {line_index},{char_index}");
-        1
-    } else {
-        0
+    /// Also find words written backwards in the searched directions.
+    fn reversed(mut self) -> Self {
+        for direction in self.directions.clone() {
+            if !self.directions.contains(&direction.opposite()) {
+                self.directions.push(direction.opposite());
+            }
+        }
+        self
     }
-}
 
-fn count_xmas(grid: &Grid, line: usize, c: usize) -> usize {
-    let mut count = 0;
-    for velocity_x in -1..=1 {
-        for velocity_y in -1..=1 {
-            if velocity_x == 0 && velocity_y == 0 {
-                continue;
+    fn wrap_around(mut self) -> Self {
+        self.wrap_around = true;
+        self
+    }
+
+    fn find(&self, grid: &Grid) -> Vec<WordMatch<'_>> {
+        let mut matches = Vec::new();
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                for direction in &self.directions {
+                    self.find_from(grid, (x, y), *direction, &mut matches);
+                }
+            }
+        }
+
+        matches
+    }
+
+    fn find_from<'a>(
+        &'a self,
+        grid: &Grid,
+        start: (usize, usize),
+        direction: Direction,
+        matches: &mut Vec<WordMatch<'a>>,
+    ) {
+        let (step_x, step_y) = direction.offset();
+        let (mut x, mut y) = start;
+        let mut node = 0;
+
+        loop {
+            node = match self.trie.nodes[node].children.get(&grid.values[y][x]) {
+                Some(child) => *child,
+                None => return,
+            };
+            for word in &self.trie.nodes[node].words {
+                matches.push(WordMatch {
+                    start,
+                    direction,
+                    word: &self.words[*word],
+                });
+            }
+
+            let next_x = x as isize + step_x;
+            let next_y = y as isize + step_y;
+            if self.wrap_around {
+                x = next_x.rem_euclid(grid.width as isize) as usize;
+                y = next_y.rem_euclid(grid.height as isize) as usize;
+            } else if (0..grid.width as isize).contains(&next_x)
+                && (0..grid.height as isize).contains(&next_y)
+            {
+                x = next_x as usize;
+                y = next_y as usize;
+            } else {
+                return;
             }
-            count += count_xmas_direction(grid, line, c, velocity_x, velocity_y);
         }
     }
+}
+
+pub fn day4_part1(input: String) -> usize {
+    let grid = phase("parse_char_grid", || parse_char_grid(input));
 
-    count
+    phase("WordSearch::find", || {
+        WordSearch::new(&["XMAS"]).find(&grid).len()
+    })
 }
 
-fn count_xmas_direction(
-    grid: &Grid,
-    line_index: usize,
-    char_index: usize,
-    velocity_x: i8,
-    velocity_y: i8,
-) -> usize {
-    if !check_bound(grid.width, char_index, velocity_x)
-        || !check_bound(grid.height, line_index, velocity_y)
-    {
-        println!("Skip {line_index},{char_index} ({velocity_x}, {velocity_y})");
-        return 0;
+pub fn day4_search(
+    input: String,
+    words: &[String],
+    directions: &[Direction],
+    reversed: bool,
+    wrap_around: bool,
+) -> String {
+    let grid = phase("parse_char_grid", || parse_char_grid(input));
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    let mut search = WordSearch::new(&words);
+    if !directions.is_empty() {
+        search = search.directions(directions);
+    }
+    if reversed {
+        search = search.reversed();
+    }
+    if wrap_around {
+        search = search.wrap_around();
+    }
+
+    let matches = phase("WordSearch::find", || search.find(&grid));
+    let mut output = String::new();
+    for word_match in &matches {
+        writeln!(
+            output,
+            "{} at {},{} going {:?}",
+            word_match.word, word_match.start.0, word_match.start.1, word_match.direction
+        )
+        .unwrap();
     }
+    writeln!(output, "{} matches", matches.len()).unwrap();
+
+    output
+}
+
+/// A small 2D pattern where `.` matches any letter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stencil {
+    rows: Vec<Vec<Option<char>>>,
+}
+
+impl FromStr for Stencil {
+    type Err = String;
 
-    let expected_chars = ['X', 'M', 'A', 'S'];
+    /// Rows are separated by `/` or line breaks, e.g. `M.S / .A. / M.S`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows: Vec<Vec<Option<char>>> = s
+            .split(['/', '\n'])
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .map(|row| {
+                row.chars()
+                    .map(|c| if c == '.' { None } else { Some(c) })
+                    .collect()
+            })
+            .collect();
 
-    for i in 1..4 {
-        let check_line = (line_index as isize) + (i * velocity_y as isize);
-        let check_char = (char_index as isize) + (i * velocity_x as isize);
-        if grid.values[check_line as usize][check_char as usize] != expected_chars[i as usize] {
-            println!("Failed {line_index},{char_index} ({velocity_x}, {velocity_y}) on {check_line},{check_char}");
-            return 0;
+        if rows.is_empty() {
+            return Err("The stencil is empty".to_string());
         }
+        if rows.iter().any(|row| row.len() != rows[0].len()) {
+            return Err(format!("All rows of the stencil {s:?} need the same width"));
+        }
+
+        Ok(Stencil { rows })
+    }
+}
+
+impl Stencil {
+    fn width(&self) -> usize {
+        self.rows[0].len()
+    }
+
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn rotate(&self) -> Stencil {
+        let rows = (0..self.width())
+            .map(|x| (0..self.height()).rev().map(|y| self.rows[y][x]).collect())
+            .collect();
+
+        Stencil { rows }
+    }
+
+    fn reflect(&self) -> Stencil {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| row.iter().rev().copied().collect())
+            .collect();
+
+        Stencil { rows }
+    }
+
+    /// All distinct rotations and reflections of the stencil.
+    fn variants(&self) -> Vec<Stencil> {
+        let mut variants: Vec<Stencil> = Vec::new();
+        let mut rotated = self.clone();
+        for _ in 0..4 {
+            for variant in [rotated.reflect(), rotated.clone()] {
+                if !variants.contains(&variant) {
+                    variants.push(variant);
+                }
+            }
+            rotated = rotated.rotate();
+        }
+        variants.reverse();
+
+        variants
+    }
+
+    fn matches_at(&self, grid: &Grid, x: usize, y: usize) -> bool {
+        x + self.width() <= grid.width
+            && y + self.height() <= grid.height
+            && self.rows.iter().enumerate().all(|(dy, row)| {
+                row.iter()
+                    .enumerate()
+                    .all(|(dx, c)| c.is_none_or(|c| grid.values[y + dy][x + dx] == c))
+            })
+    }
+
+    /// Top left positions (x, y) of all matches, once per matching variant.
+    fn find(&self, grid: &Grid, transforms: bool) -> Vec<(usize, usize)> {
+        let variants = match transforms {
+            true => self.variants(),
+            false => vec![self.clone()],
+        };
+
+        let mut matches = Vec::new();
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                for variant in &variants {
+                    if variant.matches_at(grid, x, y) {
+                        matches.push((x, y));
+                    }
+                }
+            }
+        }
+
+        matches
     }
+}
+
+pub fn day4_part2(input: String) -> usize {
+    let grid = phase("parse_char_grid", || parse_char_grid(input));
+    let x_mas: Stencil = "M.S/.A./M.S".parse().unwrap();
 
-    println!("Found {line_index},{char_index} ({velocity_x}, {velocity_y})");
-    1
+    phase("Stencil::find", || x_mas.find(&grid, true).len())
 }
 
-fn check_bound(max: usize, start: usize, direction: i8) -> bool {
-    match direction {
-        0 => true,
-        i8::MIN..0 => ((4 * direction).unsigned_abs() as usize) <= start + 1,
-        1..=i8::MAX => (4 * direction) as usize + start <= max,
+pub fn day4_stencil(input: String, stencil: &Stencil, transforms: bool) -> String {
+    let grid = phase("parse_char_grid", || parse_char_grid(input));
+
+    let matches = phase("Stencil::find", || stencil.find(&grid, transforms));
+    let mut output = String::new();
+    for (x, y) in &matches {
+        writeln!(output, "Match at {x},{y}").unwrap();
     }
+    writeln!(output, "{} matches", matches.len()).unwrap();
+
+    output
 }
 
 fn parse_char_grid(input: String) -> Grid {
-    let values: Vec<Vec<char>> = input
-        .trim()
+    let values: Vec<Vec<char>> = normalize_input(&input)
         .lines()
         .map(|line| line.chars().collect())
         .collect();
@@ -135,7 +383,7 @@ fn parse_char_grid(input: String) -> Grid {
 
 #[cfg(test)]
 mod test {
-    use crate::day4::{day4_part1, day4_part2};
+    use crate::day4::{day4_part1, day4_part2, parse_char_grid, Direction, Stencil, WordSearch};
 
     #[test]
     fn test_part1() {
@@ -178,4 +426,64 @@ MXMXAXMASX"#
             )
         )
     }
+
+    #[test]
+    fn test_word_search() {
+        let grid = parse_char_grid("ABC\nDEF\nGHI".to_string());
+
+        let search = WordSearch::new(&["AEI", "CBA", "FD", "AB", "ABC"]);
+        let matches: Vec<_> = search
+            .find(&grid)
+            .into_iter()
+            .map(|m| (m.start, m.direction, m.word))
+            .collect();
+        assert_eq!(
+            vec![
+                ((0, 0), Direction::East, "AB"),
+                ((0, 0), Direction::East, "ABC"),
+                ((0, 0), Direction::SouthEast, "AEI"),
+                ((2, 0), Direction::West, "CBA"),
+            ],
+            matches
+        );
+
+        let search = WordSearch::new(&["CBA", "FD"])
+            .directions(&[Direction::East])
+            .reversed()
+            .wrap_around();
+        let matches: Vec<_> = search
+            .find(&grid)
+            .into_iter()
+            .map(|m| (m.start, m.direction, m.word))
+            .collect();
+        assert_eq!(
+            vec![
+                ((2, 0), Direction::West, "CBA"),
+                ((2, 1), Direction::East, "FD"),
+            ],
+            matches
+        );
+    }
+
+    #[test]
+    fn test_stencil() {
+        let grid = parse_char_grid(".M.\nMAM\n.M.\nAAA".to_string());
+
+        let plus: Stencil = ".M./MAM/.M.".parse().unwrap();
+        assert_eq!(vec![(0, 0)], plus.find(&grid, false));
+
+        let corner: Stencil = "M/A".parse().unwrap();
+        assert_eq!(vec![(1, 0), (1, 2)], corner.find(&grid, false));
+        assert_eq!(4, corner.variants().len());
+        assert_eq!(
+            vec![(1, 0), (0, 1), (1, 1), (1, 1), (1, 2)],
+            corner.find(&grid, true)
+        );
+
+        let x_mas: Stencil = "M.S\n.A.\nM.S".parse().unwrap();
+        assert_eq!(4, x_mas.variants().len());
+
+        assert!("MM/A".parse::<Stencil>().is_err());
+        assert!(" / ".parse::<Stencil>().is_err());
+    }
 }
@@ -1,6 +1,16 @@
 use std::{
-    collections::{HashMap, HashSet},
-    num::ParseIntError,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use itertools::Itertools;
+use log::{error, warn};
+
+use crate::{
+    profile::phase,
+    util::{normalize_input, parse_sections},
 };
 
 #[derive(Debug)]
@@ -15,88 +25,87 @@ struct PrintingInstructions {
     updates: Vec<Vec<usize>>,
 }
 
-struct SortablePage<'a> {
-    page: usize,
-    orders: &'a HashMap<usize, Vec<Ordering>>,
+/// Pages whose rules require each of them to come before the next one, closing a loop.
+#[derive(Debug, PartialEq, Eq)]
+struct Cycle {
+    pages: Vec<usize>,
 }
 
-impl PartialEq for SortablePage<'_> {
-    fn eq(&self, other: &Self) -> bool {
-        self.page == other.page
+impl Display for Cycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for page in &self.pages {
+            write!(f, "{page} -> ")?;
+        }
+        write!(f, "{}", self.pages[0])
     }
 }
 
-impl Ord for SortablePage<'_> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.orders.get(&self.page) {
-            Some(rules) => {
-                for rule in rules {
-                    match rule {
-                        Ordering::After(after) if *after == other.page => {
-                            return std::cmp::Ordering::Less;
-                        }
-                        Ordering::Before(after) if *after == other.page => {
-                            return std::cmp::Ordering::Greater;
-                        }
-                        _ => {}
-                    }
-                }
-                std::cmp::Ordering::Equal
-            }
-            None => std::cmp::Ordering::Equal,
-        }
-    }
+#[derive(Debug, PartialEq, Eq)]
+struct FixedUpdate {
+    pages: Vec<usize>,
+    /// Whether the rules allow more than one order of the pages
+    ambiguous: bool,
 }
 
-impl PartialOrd for SortablePage<'_> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+/// The `a|b` page ordering rules
+struct Rules(Vec<(usize, usize)>);
+
+impl FromStr for Rules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .map(|line| {
+                let (before, after) = line
+                    .split_once('|')
+                    .ok_or_else(|| format!("Expected a rule like 47|53, found {line:?}"))?;
+                let parse = |page: &str| page.parse().map_err(|e| format!("{line:?}: {e}"));
+                Ok((parse(before)?, parse(after)?))
+            })
+            .collect::<Result<_, _>>()
+            .map(Rules)
     }
 }
 
-impl Eq for SortablePage<'_> {}
+/// The comma separated pages of each update
+struct Updates(Vec<Vec<usize>>);
+
+impl FromStr for Updates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .map(|line| {
+                line.split(',')
+                    .map(|page| page.parse().map_err(|e| format!("{line:?}: {e}")))
+                    .collect()
+            })
+            .collect::<Result<_, _>>()
+            .map(Updates)
+    }
+}
 
 impl PrintingInstructions {
     fn from_input(input: String) -> Self {
-        let parts: Vec<&str> = input.trim().split("\n\n").collect();
-        let orderings = parts[0].split("\n");
-        let updates = parts[1].split("\n");
+        let input = normalize_input(&input);
+        let (Rules(rules), Updates(updates)) =
+            parse_sections(&input).unwrap_or_else(|e| panic!("{e}"));
 
         let mut order_map = HashMap::<usize, Vec<Ordering>>::new();
-        let mut updates_list = Vec::<Vec<usize>>::new();
-
-        for ordering in orderings {
-            let numbers = ordering
-                .split("|")
-                .map(|n| n.parse::<usize>())
-                .collect::<Result<Vec<usize>, ParseIntError>>()
-                .unwrap();
-
-            if let Some(existing) = order_map.get_mut(&numbers[0]) {
-                existing.push(Ordering::Before(numbers[1]));
-            } else {
-                order_map.insert(numbers[0], vec![Ordering::Before(numbers[1])]);
-            }
-            if let Some(existing) = order_map.get_mut(&numbers[1]) {
-                existing.push(Ordering::After(numbers[0]));
-            } else {
-                order_map.insert(numbers[1], vec![Ordering::After(numbers[0])]);
-            }
-        }
-
-        for update in updates {
-            let numbers = update
-                .split(",")
-                .map(|n| n.parse::<usize>())
-                .collect::<Result<Vec<usize>, ParseIntError>>()
-                .unwrap();
-
-            updates_list.push(numbers);
+        for (before, after) in rules {
+            order_map
+                .entry(before)
+                .or_default()
+                .push(Ordering::Before(after));
+            order_map
+                .entry(after)
+                .or_default()
+                .push(Ordering::After(before));
         }
 
         Self {
             orders: order_map,
-            updates: updates_list,
+            updates,
         }
     }
 
@@ -108,15 +117,26 @@ impl PrintingInstructions {
             .collect()
     }
 
-    fn get_fixed_updates(&self) -> Vec<Vec<usize>> {
+    fn get_fixed_updates(&self) -> Vec<Result<FixedUpdate, Cycle>> {
         self.updates
-            .clone()
-            .into_iter()
+            .iter()
             .filter(|update| !self.is_valid_update(update))
-            .map(|update| self.fix_ordering(&update))
+            .map(|update| self.fix_ordering(update))
             .collect()
     }
 
+    /// Pages which have to be printed after `page`.
+    fn pages_after(&self, page: usize) -> impl Iterator<Item = usize> + '_ {
+        self.orders
+            .get(&page)
+            .into_iter()
+            .flatten()
+            .filter_map(|rule| match rule {
+                Ordering::Before(before) => Some(*before),
+                Ordering::After(_) => None,
+            })
+    }
+
     fn is_valid_update(&self, update: &Vec<usize>) -> bool {
         let mut forbidden_following: HashSet<usize> = HashSet::new();
         for number in update {
@@ -136,25 +156,577 @@ impl PrintingInstructions {
         true
     }
 
-    fn fix_ordering(&self, update: &[usize]) -> Vec<usize> {
-        let mut result: Vec<SortablePage<'_>> = update
+    /// Order the pages of the update with a topological sort over the rules between them.
+    /// Unrelated pages keep their relative order from the update.
+    fn fix_ordering(&self, update: &[usize]) -> Result<FixedUpdate, Cycle> {
+        self.sort_pages(update, &[])
+    }
+
+    /// Topological sort of the update where the `pinned` pages additionally keep their order.
+    /// A page listed more than once is printed that often in a row.
+    fn sort_pages(&self, update: &[usize], pinned: &[usize]) -> Result<FixedUpdate, Cycle> {
+        let distinct: Vec<usize> = update.iter().copied().unique().collect();
+        let mut copies: HashMap<usize, usize> = HashMap::new();
+        for page in update {
+            *copies.entry(*page).or_insert(0) += 1;
+        }
+        let mut incoming: HashMap<usize, usize> = distinct.iter().map(|page| (*page, 0)).collect();
+        for page in &distinct {
+            for after in self.pages_after(*page) {
+                if let Some(count) = incoming.get_mut(&after) {
+                    *count += 1;
+                }
+            }
+        }
+        for page in pinned.iter().skip(1) {
+            *incoming.get_mut(page).unwrap() += 1;
+        }
+
+        let mut result = Vec::with_capacity(update.len());
+        let mut ambiguous = false;
+        while result.len() < update.len() {
+            let mut ready = distinct
+                .iter()
+                .filter(|page| incoming.get(page) == Some(&0));
+            let Some(&page) = ready.next() else {
+                return Err(self.find_cycle(&incoming));
+            };
+            ambiguous |= ready.next().is_some();
+
+            incoming.remove(&page);
+            let next_pinned = pinned
+                .iter()
+                .position(|pinned| *pinned == page)
+                .and_then(|i| pinned.get(i + 1).copied());
+            for after in self.pages_after(page).chain(next_pinned) {
+                if let Some(count) = incoming.get_mut(&after) {
+                    *count -= 1;
+                }
+            }
+            result.extend(std::iter::repeat_n(page, copies[&page]));
+        }
+
+        Ok(FixedUpdate {
+            pages: result,
+            ambiguous,
+        })
+    }
+
+    /// Follow the rules between the pages left over by the topological sort until a page repeats.
+    fn find_cycle(&self, remaining: &HashMap<usize, usize>) -> Cycle {
+        let mut page = *remaining.keys().min().unwrap();
+        let mut path = Vec::new();
+        while !path.contains(&page) {
+            path.push(page);
+            page = self
+                .pages_after(page)
+                .filter(|after| remaining.contains_key(after))
+                .min()
+                .unwrap();
+        }
+
+        let start = path.iter().position(|p| *p == page).unwrap();
+        Cycle {
+            pages: path.split_off(start),
+        }
+    }
+}
+
+/// A rule `before|after` whose pages are printed in the wrong order.
+#[derive(Debug, PartialEq, Eq)]
+struct Violation {
+    before: usize,
+    after: usize,
+    before_index: usize,
+    after_index: usize,
+}
+
+/// Taking a page out of the update at `from` and inserting it at index `to` of the fixed update.
+#[derive(Debug, PartialEq, Eq)]
+struct Move {
+    page: usize,
+    from: usize,
+    to: usize,
+}
+
+#[derive(Debug)]
+struct UpdateAudit {
+    update: Vec<usize>,
+    violations: Vec<Violation>,
+    /// The fewest pages to move to fix the update, empty if the rules are cyclic
+    moves: Vec<Move>,
+    /// Rules between pages of the update which are implied by other rules and
+    /// therefore have no effect on the order
+    redundant_rules: Vec<(usize, usize)>,
+    cycle: Option<Cycle>,
+}
+
+impl PrintingInstructions {
+    /// Rules between the pages of the update as pairs of indices into the update.
+    fn update_rules(&self, update: &[usize]) -> Vec<(usize, usize)> {
+        let mut indices: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, page) in update.iter().enumerate() {
+            indices.entry(*page).or_default().push(i);
+        }
+
+        update
+            .iter()
+            .enumerate()
+            .flat_map(|(i, page)| {
+                self.pages_after(*page)
+                    .flat_map(|after| indices.get(&after).into_iter().flatten())
+                    .filter(move |j| **j != i)
+                    .map(move |j| (i, *j))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn audit(&self, update: &[usize]) -> UpdateAudit {
+        let rules = self.update_rules(update);
+
+        // reachable[i][j]: the page at index i has to be printed before the one at index j
+        let n = update.len();
+        let mut reachable = vec![vec![false; n]; n];
+        for (i, j) in &rules {
+            reachable[*i][*j] = true;
+        }
+        for k in 0..n {
+            for i in 0..n {
+                for j in 0..n {
+                    reachable[i][j] |= reachable[i][k] && reachable[k][j];
+                }
+            }
+        }
+
+        let violations = rules
+            .iter()
+            .filter(|(i, j)| i > j)
+            .map(|(i, j)| Violation {
+                before: update[*i],
+                after: update[*j],
+                before_index: *i,
+                after_index: *j,
+            })
+            .collect();
+        let redundant_rules = rules
             .iter()
-            .map(|page| SortablePage {
-                page: *page,
-                orders: &self.orders,
+            .filter(|(i, j)| {
+                (0..n).any(|k| k != *i && k != *j && reachable[*i][k] && reachable[k][*j])
             })
+            .map(|(i, j)| (update[*i], update[*j]))
             .collect();
 
-        result.sort();
+        let (moves, cycle) = match self.fix_ordering(update) {
+            Ok(_) => {
+                let kept = largest_compatible_set(&reachable);
+                let pinned: Vec<usize> = kept.iter().map(|i| update[*i]).unique().collect();
+                let fixed = self.sort_pages(update, &pinned).unwrap();
+                // The k-th copy of a page in the update ends up as its k-th copy in the fix
+                let mut copies: HashMap<usize, usize> = HashMap::new();
+                let mut moves = Vec::new();
+                for (i, page) in update.iter().enumerate() {
+                    let copy = copies.entry(*page).or_insert(0);
+                    if !kept.contains(&i) {
+                        moves.push(Move {
+                            page: *page,
+                            from: i,
+                            to: fixed
+                                .pages
+                                .iter()
+                                .positions(|p| p == page)
+                                .nth(*copy)
+                                .unwrap(),
+                        });
+                    }
+                    *copy += 1;
+                }
+                (moves, None)
+            }
+            Err(cycle) => (Vec::new(), Some(cycle)),
+        };
+
+        UpdateAudit {
+            update: update.to_vec(),
+            violations,
+            moves,
+            redundant_rules,
+            cycle,
+        }
+    }
+}
+
+/// Largest set of update indices whose pages can stay in place: no rule requires a later
+/// one of them before an earlier one. `reachable` is the transitive closure of the rules.
+///
+/// A page that has to be printed before an earlier page conflicts with it. Conflicts are
+/// transitive, so the result is the largest antichain of the conflict order. By Dilworth's
+/// and König's theorems it is found through a maximum matching between the pages and the
+/// pages they conflict with.
+fn largest_compatible_set(reachable: &[Vec<bool>]) -> Vec<usize> {
+    let n = reachable.len();
+    let conflicts = |a: usize, b: usize| b < a && reachable[a][b];
+
+    // matches[b]: the page matched to the page b it conflicts with
+    let mut matches: Vec<Option<usize>> = vec![None; n];
+    for a in 0..n {
+        augment(a, &conflicts, &mut matches, &mut vec![false; n]);
+    }
+    let mut matched_left = vec![false; n];
+    for a in matches.iter().flatten() {
+        matched_left[*a] = true;
+    }
+
+    // Alternating paths starting at the unmatched pages
+    let mut left: Vec<bool> = matched_left.iter().map(|matched| !matched).collect();
+    let mut right = vec![false; n];
+    let mut queue: VecDeque<usize> = (0..n).filter(|a| left[*a]).collect();
+    while let Some(a) = queue.pop_front() {
+        for b in (0..n).filter(|b| conflicts(a, *b)) {
+            if !right[b] {
+                right[b] = true;
+                if let Some(next) = matches[b].filter(|next| !left[*next]) {
+                    left[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    (0..n).filter(|i| left[*i] && !right[*i]).collect()
+}
+
+/// Find an augmenting path from the page `a` to an unmatched page it conflicts with.
+fn augment(
+    a: usize,
+    conflicts: &impl Fn(usize, usize) -> bool,
+    matches: &mut [Option<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for b in 0..matches.len() {
+        if conflicts(a, b) && !visited[b] {
+            visited[b] = true;
+            if matches[b].is_none_or(|other| augment(other, conflicts, matches, visited)) {
+                matches[b] = Some(a);
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AuditFormat {
+    Text,
+    Json,
+}
+
+impl UpdateAudit {
+    fn write_text(&self, output: &mut String, number: usize) {
+        let update = self.update.iter().join(",");
+        if self.violations.is_empty() {
+            writeln!(output, "Update {number} ({update}): valid").unwrap();
+        } else {
+            writeln!(
+                output,
+                "Update {number} ({update}): {} violated rules",
+                self.violations.len()
+            )
+            .unwrap();
+        }
+        for violation in &self.violations {
+            writeln!(
+                output,
+                "  violates {}|{}: {} at index {}, {} at index {}",
+                violation.before,
+                violation.after,
+                violation.before,
+                violation.before_index,
+                violation.after,
+                violation.after_index
+            )
+            .unwrap();
+        }
+        for page_move in &self.moves {
+            writeln!(
+                output,
+                "  move {} from index {} to index {} of the fixed update",
+                page_move.page, page_move.from, page_move.to
+            )
+            .unwrap();
+        }
+        if let Some(cycle) = &self.cycle {
+            writeln!(
+                output,
+                "  can not be fixed, the rules form a cycle: {cycle}"
+            )
+            .unwrap();
+        }
+        if !self.redundant_rules.is_empty() {
+            let rules = self
+                .redundant_rules
+                .iter()
+                .map(|(before, after)| format!("{before}|{after}"))
+                .join(", ");
+            writeln!(output, "  rules without effect: {rules}").unwrap();
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let pages =
+            |pages: &[usize]| Json::Array(pages.iter().map(|page| Json::Number(*page)).collect());
+
+        Json::Object(vec![
+            ("update", pages(&self.update)),
+            ("valid", Json::Bool(self.violations.is_empty())),
+            (
+                "violations",
+                Json::Array(
+                    self.violations
+                        .iter()
+                        .map(|violation| {
+                            Json::Object(vec![
+                                ("rule", pages(&[violation.before, violation.after])),
+                                (
+                                    "indices",
+                                    pages(&[violation.before_index, violation.after_index]),
+                                ),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "moves",
+                Json::Array(
+                    self.moves
+                        .iter()
+                        .map(|page_move| {
+                            Json::Object(vec![
+                                ("page", Json::Number(page_move.page)),
+                                ("from", Json::Number(page_move.from)),
+                                ("to", Json::Number(page_move.to)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "redundant_rules",
+                Json::Array(
+                    self.redundant_rules
+                        .iter()
+                        .map(|(before, after)| pages(&[*before, *after]))
+                        .collect(),
+                ),
+            ),
+            (
+                "cycle",
+                self.cycle
+                    .as_ref()
+                    .map_or(Json::Null, |cycle| pages(&cycle.pages)),
+            ),
+        ])
+    }
+}
+
+/// Minimal JSON value for the machine readable audit output.
+enum Json {
+    Null,
+    Bool(bool),
+    Number(usize),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{value}"),
+            Json::Number(value) => write!(f, "{value}"),
+            Json::Array(values) => write!(f, "[{}]", values.iter().join(",")),
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
 
-        result.into_iter().map(|p| p.page).collect()
+fn write_json_string(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
     }
+    write!(f, "\"")
+}
+
+pub fn day5_audit(input: String, format: AuditFormat) -> String {
+    let instructions = phase("PrintingInstructions::from_input", || {
+        PrintingInstructions::from_input(input)
+    });
+
+    let audits: Vec<UpdateAudit> = phase("PrintingInstructions::audit", || {
+        instructions
+            .updates
+            .iter()
+            .map(|update| instructions.audit(update))
+            .collect()
+    });
+
+    let mut output = String::new();
+    match format {
+        AuditFormat::Text => {
+            for (i, audit) in audits.iter().enumerate() {
+                audit.write_text(&mut output, i + 1);
+            }
+        }
+        AuditFormat::Json => {
+            let audits = Json::Array(audits.iter().map(UpdateAudit::to_json).collect());
+            writeln!(output, "{audits}").unwrap();
+        }
+    }
+
+    output
+}
+
+impl PrintingInstructions {
+    /// Rules as edges `before -> after`, restricted to rules between the given pages.
+    fn rule_edges(&self, pages: &BTreeSet<usize>) -> BTreeSet<(usize, usize)> {
+        pages
+            .iter()
+            .flat_map(|page| {
+                self.pages_after(*page)
+                    .filter(|after| pages.contains(after))
+                    .map(|after| (*page, after))
+            })
+            .collect()
+    }
+
+    /// Render the rules as a Graphviz digraph. When `update` is given only its pages are
+    /// included and the rules it violates are drawn in red.
+    fn to_dot(&self, update: Option<&[usize]>, transitive_reduction: bool) -> String {
+        let pages: BTreeSet<usize> = match update {
+            Some(update) => update.iter().copied().collect(),
+            None => self.orders.keys().copied().collect(),
+        };
+        let mut edges = self.rule_edges(&pages);
+        if transitive_reduction {
+            edges = reduce(&edges);
+        }
+        let violated: HashSet<(usize, usize)> = match update {
+            Some(update) => self
+                .update_rules(update)
+                .into_iter()
+                .filter(|(i, j)| i > j)
+                .map(|(i, j)| (update[i], update[j]))
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        let mut output = String::from("digraph rules {\n");
+        for page in &pages {
+            writeln!(output, "    {page};").unwrap();
+        }
+        for (before, after) in &edges {
+            if violated.contains(&(*before, *after)) {
+                writeln!(output, "    {before} -> {after} [color=red];").unwrap();
+            } else {
+                writeln!(output, "    {before} -> {after};").unwrap();
+            }
+        }
+        output.push_str("}\n");
+
+        output
+    }
+}
+
+/// Drop every edge whose pages are also connected through a longer path.
+/// Edges between pages on a common cycle are kept, so cyclic rules stay visible.
+fn reduce(edges: &BTreeSet<(usize, usize)>) -> BTreeSet<(usize, usize)> {
+    let reaches = |from: usize, to: usize, skip: Option<(usize, usize)>| {
+        let mut visited = HashSet::from([from]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(page) = queue.pop_front() {
+            for edge in edges.range((page, 0)..=(page, usize::MAX)) {
+                if Some(*edge) == skip {
+                    continue;
+                }
+                if edge.1 == to {
+                    return true;
+                }
+                if visited.insert(edge.1) {
+                    queue.push_back(edge.1);
+                }
+            }
+        }
+        false
+    };
+
+    edges
+        .iter()
+        .filter(|(before, after)| {
+            !reaches(*before, *after, Some((*before, *after))) || reaches(*after, *before, None)
+        })
+        .copied()
+        .collect()
+}
+
+pub fn day5_dot(
+    input: String,
+    update: Option<usize>,
+    transitive_reduction: bool,
+) -> Result<String, String> {
+    let instructions = phase("PrintingInstructions::from_input", || {
+        PrintingInstructions::from_input(input)
+    });
+
+    let update = match update {
+        Some(number) => match number
+            .checked_sub(1)
+            .and_then(|i| instructions.updates.get(i))
+        {
+            Some(update) => Some(update.as_slice()),
+            None => {
+                return Err(format!(
+                    "There is no update {number}, the input has {} updates",
+                    instructions.updates.len()
+                ))
+            }
+        },
+        None => None,
+    };
+
+    Ok(phase("PrintingInstructions::to_dot", || {
+        instructions.to_dot(update, transitive_reduction)
+    }))
 }
 
 pub fn day5_part1(input: String) -> usize {
-    let instructions = PrintingInstructions::from_input(input);
+    let instructions = phase("PrintingInstructions::from_input", || {
+        PrintingInstructions::from_input(input)
+    });
 
-    let valid_updates = instructions.get_valid_updates();
+    let valid_updates = phase("PrintingInstructions::get_valid_updates", || {
+        instructions.get_valid_updates()
+    });
     valid_updates
         .into_iter()
         .map(|numbers| numbers[numbers.len() / 2])
@@ -162,19 +734,319 @@ pub fn day5_part1(input: String) -> usize {
 }
 
 pub fn day5_part2(input: String) -> usize {
-    let instructions = PrintingInstructions::from_input(input);
+    let instructions = phase("PrintingInstructions::from_input", || {
+        PrintingInstructions::from_input(input)
+    });
 
-    let valid_updates = instructions.get_fixed_updates();
+    let fixed_updates = phase("PrintingInstructions::get_fixed_updates", || {
+        instructions.get_fixed_updates()
+    });
 
-    valid_updates
+    fixed_updates
         .into_iter()
+        .filter_map(|fixed| match fixed {
+            Ok(fixed) => {
+                if fixed.ambiguous {
+                    warn!("The order of {:?} is ambiguous", fixed.pages);
+                }
+                Some(fixed.pages)
+            }
+            Err(cycle) => {
+                error!("Skipping an update with cyclic rules: {cycle}");
+                None
+            }
+        })
         .map(|numbers| numbers[numbers.len() / 2])
         .sum()
 }
 
 #[cfg(test)]
 mod test {
-    use crate::day5::{day5_part1, day5_part2};
+    use std::collections::HashSet;
+
+    use itertools::Itertools;
+    use proptest::{prelude::*, sample::subsequence};
+
+    use crate::day5::{
+        day5_audit, day5_dot, day5_part1, day5_part2, AuditFormat, Cycle, FixedUpdate, Json, Move,
+        PrintingInstructions,
+    };
+
+    proptest! {
+        #[test]
+        fn fixed_ordering_respects_all_rules(
+            (order, update) in Just((10..40usize).collect::<Vec<_>>())
+                .prop_shuffle()
+                .prop_flat_map(|order| {
+                    let update = subsequence(order.clone(), 1..order.len()).prop_shuffle();
+                    (Just(order), update)
+                })
+        ) {
+            let rules = order
+                .iter()
+                .tuple_combinations()
+                .map(|(before, after)| format!("{before}|{after}"))
+                .join("\n");
+            let instructions =
+                PrintingInstructions::from_input(format!("{rules}\n\n{}", update.iter().join(",")));
+
+            let fixed = instructions.fix_ordering(&update).unwrap();
+
+            prop_assert!(!fixed.ambiguous);
+            prop_assert!(instructions.is_valid_update(&fixed.pages));
+            for (before, after) in fixed.pages.iter().tuple_combinations() {
+                let position = |page| order.iter().position(|p| p == page);
+                prop_assert!(position(before) < position(after));
+            }
+        }
+
+        #[test]
+        fn fixed_ordering_respects_sparse_rules(
+            (order, update, kept) in Just((10..30usize).collect::<Vec<_>>())
+                .prop_shuffle()
+                .prop_flat_map(|order| {
+                    let update = subsequence(order.clone(), 1..order.len()).prop_shuffle();
+                    let kept = prop::collection::vec(any::<bool>(), order.len() * (order.len() - 1) / 2);
+                    (Just(order), update, kept)
+                })
+        ) {
+            let rules = order
+                .iter()
+                .tuple_combinations()
+                .zip(kept)
+                .filter(|(_, kept)| *kept)
+                .map(|((before, after), _)| format!("{before}|{after}"))
+                .join("\n");
+            let instructions =
+                PrintingInstructions::from_input(format!("{rules}\n\n{}", update.iter().join(",")));
+
+            let fixed = instructions.fix_ordering(&update).unwrap();
+
+            prop_assert!(instructions.is_valid_update(&fixed.pages));
+            prop_assert_eq!(update.len(), fixed.pages.len());
+        }
+
+        #[test]
+        fn audit_moves_are_minimal(
+            (order, update, kept) in Just((10..17usize).collect::<Vec<_>>())
+                .prop_shuffle()
+                .prop_flat_map(|order| {
+                    let update = Just(order.clone()).prop_shuffle();
+                    let kept = prop::collection::vec(any::<bool>(), order.len() * (order.len() - 1) / 2);
+                    (Just(order), update, kept)
+                })
+        ) {
+            let rules = order
+                .iter()
+                .tuple_combinations()
+                .zip(kept)
+                .filter(|(_, kept)| *kept)
+                .map(|((before, after), _)| format!("{before}|{after}"))
+                .chain(["1|2".to_string()])
+                .join("\n");
+            let instructions =
+                PrintingInstructions::from_input(format!("{rules}\n\n{}", update.iter().join(",")));
+
+            let audit = instructions.audit(&update);
+
+            // Pages can stay in place when none of them has to be printed, directly or through
+            // other pages, before one that comes earlier in the update
+            let before = |a: usize, b: usize| {
+                let mut stack = vec![a];
+                let mut seen = HashSet::new();
+                while let Some(page) = stack.pop() {
+                    for after in instructions.pages_after(page) {
+                        if after == b {
+                            return true;
+                        }
+                        if seen.insert(after) {
+                            stack.push(after);
+                        }
+                    }
+                }
+                false
+            };
+            let fewest_moves = (0..=update.len())
+                .rev()
+                .find(|size| {
+                    update.iter().copied().combinations(*size).any(|kept| {
+                        kept.iter()
+                            .tuple_combinations()
+                            .all(|(a, b)| !before(*b, *a))
+                    })
+                })
+                .map(|size| update.len() - size)
+                .unwrap();
+            prop_assert_eq!(fewest_moves, audit.moves.len());
+        }
+    }
+
+    #[test]
+    fn test_audit_many_inverted_pairs() {
+        let pages = 200;
+        let rules = (0..pages / 2)
+            .map(|i| format!("{}|{}", 2 * i + 10, 2 * i + 11))
+            .join("\n");
+        let update = (0..pages / 2)
+            .flat_map(|i| [2 * i + 11, 2 * i + 10])
+            .collect::<Vec<usize>>();
+        let instructions =
+            PrintingInstructions::from_input(format!("{rules}\n\n{}", update.iter().join(",")));
+
+        assert_eq!(pages / 2, instructions.audit(&update).moves.len());
+    }
+
+    #[test]
+    fn test_audit_duplicate_pages() {
+        let input = "47|53\n53|61\n\n61,53,47,53";
+        let instructions = PrintingInstructions::from_input(input.to_string());
+        let audit = instructions.audit(&[61, 53, 47, 53]);
+
+        assert_eq!(3, audit.violations.len());
+        assert_eq!(2, audit.moves.len());
+        assert!(day5_audit(input.to_string(), AuditFormat::Text).contains("3 violated rules"));
+    }
+
+    #[test]
+    fn test_fix_ordering() {
+        let instructions = PrintingInstructions::from_input("1|2\n1|3\n\n3,2,1".to_string());
+        assert_eq!(
+            Ok(FixedUpdate {
+                pages: vec![1, 3, 2],
+                ambiguous: true
+            }),
+            instructions.fix_ordering(&[3, 2, 1])
+        );
+
+        let instructions =
+            PrintingInstructions::from_input("1|2\n2|3\n3|1\n3|4\n\n4,3,2,1".to_string());
+        let cycle = instructions.fix_ordering(&[4, 3, 2, 1]).unwrap_err();
+        assert_eq!(
+            Cycle {
+                pages: vec![1, 2, 3]
+            },
+            cycle
+        );
+        assert_eq!("1 -> 2 -> 3 -> 1", cycle.to_string());
+    }
+
+    #[test]
+    fn test_duplicate_pages() {
+        let input = "47|53\n53|61\n\n61,53,47,53";
+        let instructions = PrintingInstructions::from_input(input.to_string());
+        assert_eq!(
+            Ok(FixedUpdate {
+                pages: vec![47, 53, 53, 61],
+                ambiguous: false
+            }),
+            instructions.fix_ordering(&[61, 53, 47, 53])
+        );
+        assert_eq!(53, day5_part2(input.to_string()));
+    }
+
+    #[test]
+    fn test_audit() {
+        let instructions =
+            PrintingInstructions::from_input("1|2\n2|3\n1|3\n3|4\n\n2,3,4,1".to_string());
+        let audit = instructions.audit(&[2, 3, 4, 1]);
+        assert_eq!(2, audit.violations.len());
+        assert_eq!(
+            vec![Move {
+                page: 1,
+                from: 3,
+                to: 0
+            }],
+            audit.moves
+        );
+        assert_eq!(vec![(1, 3)], audit.redundant_rules);
+
+        // Moving 4 alone does not help as 4 also has to come after 3
+        let audit = instructions.audit(&[4, 1, 2, 3]);
+        assert_eq!(
+            vec![Move {
+                page: 4,
+                from: 0,
+                to: 3
+            }],
+            audit.moves
+        );
+
+        let instructions = PrintingInstructions::from_input("1|2\n2|1\n\n1,2".to_string());
+        let audit = instructions.audit(&[1, 2]);
+        assert!(audit.moves.is_empty());
+        assert!(audit.cycle.is_some());
+    }
+
+    #[test]
+    fn test_audit_output() {
+        let input = "47|53\n97|13\n97|47\n47|13\n\n97,47,53\n53,47,13\n".to_string();
+        assert_eq!(
+            "Update 1 (97,47,53): valid
+Update 2 (53,47,13): 1 violated rules
+  violates 47|53: 47 at index 1, 53 at index 0
+  move 47 from index 1 to index 0 of the fixed update
+",
+            day5_audit(input.clone(), AuditFormat::Text)
+        );
+        assert_eq!(
+            r#"[{"update":[97,47,53],"valid":true,"violations":[],"moves":[],"redundant_rules":[],"cycle":null},{"update":[53,47,13],"valid":false,"violations":[{"rule":[47,53],"indices":[1,0]}],"moves":[{"page":47,"from":1,"to":0}],"redundant_rules":[],"cycle":null}]
+"#,
+            day5_audit(input, AuditFormat::Json)
+        );
+    }
+
+    #[test]
+    fn test_json_escaping() {
+        let json = Json::Object(vec![
+            (
+                "say \"hi\"\\\n",
+                Json::Array(vec![Json::Number(1), Json::Null]),
+            ),
+            ("\u{1}", Json::Bool(false)),
+        ]);
+        assert_eq!(
+            r#"{"say \"hi\"\\\n":[1,null],"\u0001":false}"#,
+            json.to_string()
+        );
+    }
+
+    #[test]
+    fn test_dot() {
+        let input = "1|2\n2|3\n1|3\n3|4\n\n3,2\n".to_string();
+        assert_eq!(
+            "digraph rules {
+    1;
+    2;
+    3;
+    4;
+    1 -> 2;
+    2 -> 3;
+    3 -> 4;
+}
+",
+            day5_dot(input.clone(), None, true).unwrap()
+        );
+        assert_eq!(
+            "digraph rules {
+    2;
+    3;
+    2 -> 3 [color=red];
+}
+",
+            day5_dot(input.clone(), Some(1), false).unwrap()
+        );
+        assert_eq!(
+            Err("There is no update 2, the input has 1 updates".to_string()),
+            day5_dot(input.clone(), Some(2), false)
+        );
+        assert_eq!(
+            Err("There is no update 0, the input has 1 updates".to_string()),
+            day5_dot(input, Some(0), false)
+        );
+
+        let cyclic = "1|2\n2|3\n3|1\n1|3\n\n1,2,3\n".to_string();
+        assert!(day5_dot(cyclic, None, true).unwrap().contains("1 -> 3;"));
+    }
 
     #[test]
     fn test_part1() {
@@ -1,11 +1,16 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    fmt::Write,
     ops::Add,
-    thread::{self, JoinHandle},
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    thread,
 };
 
+use clap::ValueEnum;
 use log::{info, warn};
 
+use crate::{profile::phase, util::normalize_input};
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 enum Direction {
     North,
@@ -19,6 +24,8 @@ type Position = (isize, isize);
 #[derive(Debug)]
 struct Map {
     obstacles: HashSet<Position>,
+    /// All guards on the map, the first one is the guard of part 1 and 2
+    guards: Vec<(Position, Direction)>,
 
     guard_position: Option<Position>,
     guard_start_position: Position,
@@ -29,6 +36,123 @@ struct Map {
     height: isize,
 
     visited: HashSet<Position>,
+    /// The guard state right before each visited cell was entered for the first time
+    entries: Vec<Entry>,
+    jumps: JumpTable,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    cell: Position,
+    from: Position,
+    direction: Direction,
+}
+
+/// For every cell and direction the cell in which a guard walking that way stops in
+/// front of the next obstacle, or `None` if the guard leaves the map.
+#[derive(Debug, Clone)]
+struct JumpTable {
+    width: isize,
+    height: isize,
+    obstacles: Vec<bool>,
+    stops: Vec<[Option<Position>; 4]>,
+}
+
+impl JumpTable {
+    fn new(width: isize, height: isize, obstacles: &HashSet<Position>) -> Self {
+        let cells = (width * height) as usize;
+        let mut table = Self {
+            width,
+            height,
+            obstacles: vec![false; cells],
+            stops: vec![[None; 4]; cells],
+        };
+        for obstacle in obstacles {
+            let index = table.index(*obstacle);
+            table.obstacles[index] = true;
+        }
+        for y in 0..height {
+            table.update_line((0, y), Direction::East);
+            table.update_line((0, y), Direction::West);
+        }
+        for x in 0..width {
+            table.update_line((x, 0), Direction::North);
+            table.update_line((x, 0), Direction::South);
+        }
+
+        table
+    }
+
+    fn index(&self, position: Position) -> usize {
+        (position.1 * self.width + position.0) as usize
+    }
+
+    fn check_bounds(&self, position: Position) -> bool {
+        position.0 >= 0 && position.1 >= 0 && position.0 < self.width && position.1 < self.height
+    }
+
+    fn jump(&self, position: Position, direction: Direction) -> Option<Position> {
+        self.stops[self.index(position)][direction as usize]
+    }
+
+    /// Recompute the stops of the row or column through `through` for one direction,
+    /// walking backwards from the edge the guard would leave through.
+    fn update_line(&mut self, through: Position, direction: Direction) {
+        let mut position = through;
+        while self.check_bounds(position + direction) {
+            position = position + direction;
+        }
+
+        let (offset_x, offset_y) = direction.get_offset();
+        let mut stop = None;
+        while self.check_bounds(position) {
+            let index = self.index(position);
+            let previous = (position.0 - offset_x, position.1 - offset_y);
+            if self.obstacles[index] {
+                stop = Some(previous);
+            } else {
+                self.stops[index][direction as usize] = stop;
+            }
+            position = previous;
+        }
+    }
+
+    /// Only the row and the column of the obstacle have to be updated.
+    fn set_obstacle(&mut self, obstacle: Position, present: bool) {
+        let index = self.index(obstacle);
+        self.obstacles[index] = present;
+        for direction in [
+            Direction::North,
+            Direction::East,
+            Direction::South,
+            Direction::West,
+        ] {
+            self.update_line(obstacle, direction);
+        }
+    }
+
+    fn add_obstacle(&mut self, obstacle: Position) {
+        self.set_obstacle(obstacle, true);
+    }
+
+    fn remove_obstacle(&mut self, obstacle: Position) {
+        self.set_obstacle(obstacle, false);
+    }
+
+    /// Jump from turn to turn until the guard leaves the map or repeats a turn.
+    fn check_on_loop_path(&self, mut position: Position, mut direction: Direction) -> bool {
+        let mut turns: HashSet<(Position, Direction)> = HashSet::new();
+
+        while let Some(stop) = self.jump(position, direction) {
+            position = stop;
+            direction = direction.rotate();
+            if !turns.insert((position, direction)) {
+                return true;
+            }
+        }
+
+        false
+    }
 }
 
 impl Direction {
@@ -41,6 +165,25 @@ impl Direction {
         }
     }
 
+    fn from_guard(value: char) -> Option<Self> {
+        match value {
+            '^' => Some(Direction::North),
+            '>' => Some(Direction::East),
+            'v' => Some(Direction::South),
+            '<' => Some(Direction::West),
+            _ => None,
+        }
+    }
+
+    fn to_guard(self) -> char {
+        match self {
+            Direction::North => '^',
+            Direction::East => '>',
+            Direction::South => 'v',
+            Direction::West => '<',
+        }
+    }
+
     fn rotate(&self) -> Self {
         match self {
             Direction::North => Direction::East,
@@ -63,10 +206,11 @@ impl Add<Direction> for Position {
 impl Map {
     fn parse_input(input: String) -> Self {
         let mut obstacles = HashSet::<Position>::new();
-        let mut guard_position: Option<Position> = None;
+        let mut guards = Vec::<(Position, Direction)>::new();
         let mut width = 0;
 
-        let lines = input.split("\n");
+        let input = normalize_input(&input);
+        let lines = input.lines();
         let height = lines.clone().count() as isize;
 
         for (row_index, row) in lines.enumerate() {
@@ -79,19 +223,25 @@ impl Map {
                 if value == '#' {
                     obstacles.insert((col_index, row_index));
                 }
-                if value == '^' {
-                    guard_position = Some((col_index, row_index));
+                if let Some(direction) = Direction::from_guard(value) {
+                    guards.push(((col_index, row_index), direction));
                 }
             }
         }
 
+        let (guard_start_position, guard_start_direction) =
+            *guards.first().expect("Expected a guard on the map");
+
         Self {
+            jumps: JumpTable::new(width, height, &obstacles),
             obstacles,
             visited: HashSet::<Position>::new(),
-            guard_position,
-            guard_start_position: guard_position.unwrap(),
-            guard_direction: Direction::North,
-            guard_start_direction: Direction::North,
+            entries: Vec::new(),
+            guards,
+            guard_position: Some(guard_start_position),
+            guard_start_position,
+            guard_direction: guard_start_direction,
+            guard_start_direction,
 
             height,
             width,
@@ -99,83 +249,91 @@ impl Map {
     }
 
     fn simulate_all_steps(&mut self) {
-        let max_iterations = 999_999_999;
+        let max_turns = 4 * self.width * self.height;
 
-        for i in 0..max_iterations {
-            if let Some(guard_position) = self.guard_position {
-                let new_position = guard_position + self.guard_direction;
+        for i in 0..max_turns {
+            let Some(mut position) = self.guard_position else {
+                return;
+            };
+            let stop = self.jumps.jump(position, self.guard_direction);
 
-                if !self.check_bounds(new_position) {
+            while Some(position) != stop {
+                let from = position;
+                position = position + self.guard_direction;
+                if !self.check_bounds(position) {
                     self.guard_position = None;
-                    info!("Done after {i} iterations");
+                    info!("Done after {i} turns");
                     return;
-                } else if self.obstacles.contains(&new_position) {
-                    self.guard_direction = self.guard_direction.rotate();
-                } else {
-                    self.visited.insert(new_position);
-                    self.guard_position = Some(new_position);
+                }
+                if self.visited.insert(position) {
+                    self.entries.push(Entry {
+                        cell: position,
+                        from,
+                        direction: self.guard_direction,
+                    });
                 }
             }
+            self.guard_position = Some(position);
+            self.guard_direction = self.guard_direction.rotate();
         }
 
-        warn!("Abort after {max_iterations} iterations");
+        warn!("Abort after {max_turns} turns");
     }
 
     fn check_bounds(&self, position: Position) -> bool {
         position.0 >= 0 && position.1 >= 0 && position.0 < self.width && position.1 < self.height
     }
 
-    fn check_for_loops(&self) -> usize {
-        let mut threads: Vec<JoinHandle<usize>> = Vec::with_capacity(self.height as usize);
-
-        for y in 0..self.height {
-            let template = Map {
-                guard_direction: self.guard_direction,
-                guard_position: self.guard_position,
-                guard_start_direction: self.guard_start_direction,
-                guard_start_position: self.guard_start_position,
-                height: self.height,
-                obstacles: self.obstacles.clone(),
-                visited: HashSet::new(),
-                width: self.width,
-            };
-            let thread = thread::spawn(move || {
-                let mut sum: usize = 0;
-
-                for x in 0..template.width {
-                    let mut obstacles = template.obstacles.clone();
-                    obstacles.insert((x, y));
-
-                    let mut simulation = Map {
-                        guard_direction: template.guard_direction,
-                        guard_position: template.guard_position,
-                        guard_start_direction: template.guard_start_direction,
-                        guard_start_position: template.guard_start_position,
-                        height: template.height,
-                        obstacles,
-                        visited: HashSet::new(),
-                        width: template.width,
-                    };
-
-                    if simulation.check_on_loop_path() {
-                        sum += 1;
-                    }
-                }
-                sum
-            });
-            threads.push(thread);
-        }
-
-        let mut total = 0;
-        for thread in threads {
-            total += thread.join().unwrap();
-        }
-        total
+    /// Positions where a new obstacle makes the guard loop. Only cells on the original
+    /// route can change the path, and each is tested starting right before the guard
+    /// would first walk into it. Requires `simulate_all_steps` to have run.
+    fn check_for_loops(&self) -> Vec<Position> {
+        let candidates: Vec<Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.cell != self.guard_start_position)
+            .copied()
+            .collect();
+        let workers = thread::available_parallelism()
+            .map_or(1, |workers| workers.get())
+            .min(candidates.len());
+        let next_candidate = AtomicUsize::new(0);
+
+        let mut loops: Vec<Position> = thread::scope(|scope| {
+            let threads: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        phase("Map::check_for_loops worker", || {
+                            let mut jumps = self.jumps.clone();
+                            let mut loops = Vec::new();
+                            while let Some(entry) =
+                                candidates.get(next_candidate.fetch_add(1, Relaxed))
+                            {
+                                jumps.add_obstacle(entry.cell);
+                                if jumps.check_on_loop_path(entry.from, entry.direction) {
+                                    loops.push(entry.cell);
+                                }
+                                jumps.remove_obstacle(entry.cell);
+                            }
+                            loops
+                        })
+                    })
+                })
+                .collect();
+
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        loops.sort_by_key(|(x, y)| (*y, *x));
+
+        loops
     }
 
-    fn render(&self) {
+    fn render(&self) -> String {
+        let mut output = String::new();
         for y in 0..self.height {
-            let mut output = String::new();
             for x in 0..self.width {
                 if self.guard_start_position == (x, y) {
                     output += match self.guard_start_direction {
@@ -192,56 +350,411 @@ impl Map {
                     output += ".";
                 }
             }
-            println!("{}", output);
+            output += "\n";
+        }
+        output
+    }
+}
+
+/// How a guard turns when it hits an obstacle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TurnRule {
+    Right,
+    Left,
+    Reverse,
+}
+
+impl TurnRule {
+    fn turn(&self, direction: Direction) -> Direction {
+        match self {
+            TurnRule::Right => direction.rotate(),
+            TurnRule::Left => direction.rotate().rotate().rotate(),
+            TurnRule::Reverse => direction.rotate().rotate(),
         }
-        println!("-----");
     }
+}
 
-    fn check_on_loop_path(&mut self) -> bool {
-        let max_iterations: u64 = 999_999_999_999_999;
-        let mut visit_directions: HashSet<(Position, Direction)> = HashSet::new();
+/// What happens when a guard would walk into the cell of another guard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Collision {
+    /// Guards walk through each other
+    Ignore,
+    /// Guards treat each other like obstacles
+    Block,
+    /// Both guards stop where they are
+    Stop,
+}
 
-        for _ in 0..max_iterations {
-            if let Some(guard_position) = self.guard_position {
-                let new_position = guard_position + self.guard_direction;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// The guard left the map from this cell
+    Exited(Position),
+    Cycle,
+    Stopped(Position),
+}
 
-                if !self.check_bounds(new_position) {
-                    self.guard_position = None;
-                    return false;
-                } else if self.obstacles.contains(&new_position) {
-                    self.guard_direction = self.guard_direction.rotate();
-                } else if visit_directions.contains(&(new_position, self.guard_direction)) {
-                    return true;
-                } else {
-                    visit_directions.insert((new_position, self.guard_direction));
+#[derive(Debug)]
+struct GuardReport {
+    start: Position,
+    direction: Direction,
+    /// Visited cells including the start
+    visited: HashSet<Position>,
+    outcome: Outcome,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Guard {
+    position: Position,
+    direction: Direction,
+    active: bool,
+}
+
+impl Map {
+    /// Let all guards patrol at the same time, one step or turn per guard and tick.
+    fn patrol(&self, turn_rule: TurnRule, collision: Collision) -> Vec<GuardReport> {
+        match collision {
+            // Without interactions every guard can be simulated on its own
+            Collision::Ignore => self
+                .guards
+                .iter()
+                .flat_map(|guard| self.patrol_together(&[*guard], turn_rule, collision))
+                .collect(),
+            Collision::Block | Collision::Stop => {
+                self.patrol_together(&self.guards, turn_rule, collision)
+            }
+        }
+    }
 
-                    self.visited.insert(new_position);
-                    self.guard_position = Some(new_position);
+    /// Guards in a cycle are detected once the state of all guards repeats. The joint period
+    /// can be much longer than the period of every single guard, so instead of remembering
+    /// all states the current one is compared to a snapshot that is retaken after every
+    /// power of two ticks (Brent's cycle detection).
+    fn patrol_together(
+        &self,
+        starts: &[(Position, Direction)],
+        turn_rule: TurnRule,
+        collision: Collision,
+    ) -> Vec<GuardReport> {
+        let mut guards: Vec<Guard> = starts
+            .iter()
+            .map(|(position, direction)| Guard {
+                position: *position,
+                direction: *direction,
+                active: true,
+            })
+            .collect();
+        let mut reports: Vec<GuardReport> = starts
+            .iter()
+            .map(|(position, direction)| GuardReport {
+                start: *position,
+                direction: *direction,
+                visited: HashSet::from([*position]),
+                outcome: Outcome::Cycle,
+            })
+            .collect();
+        let mut snapshot = guards.clone();
+        let mut power = 1;
+        let mut ticks = 0;
+
+        while guards.iter().any(|guard| guard.active) {
+            for i in 0..guards.len() {
+                if !guards[i].active {
+                    continue;
                 }
+                let next = guards[i].position + guards[i].direction;
+                // Exited guards are no longer on the map, stopped ones still are
+                let other = (0..guards.len()).find(|j| {
+                    *j != i
+                        && guards[*j].position == next
+                        && !matches!(reports[*j].outcome, Outcome::Exited(_))
+                });
+
+                if !self.check_bounds(next) {
+                    guards[i].active = false;
+                    reports[i].outcome = Outcome::Exited(guards[i].position);
+                } else if self.obstacles.contains(&next)
+                    || (collision == Collision::Block && other.is_some())
+                {
+                    guards[i].direction = turn_rule.turn(guards[i].direction);
+                } else if let (Collision::Stop, Some(j)) = (collision, other) {
+                    for k in [i, j] {
+                        guards[k].active = false;
+                        reports[k].outcome = Outcome::Stopped(guards[k].position);
+                    }
+                } else {
+                    guards[i].position = next;
+                    reports[i].visited.insert(next);
+                }
+            }
+
+            if guards == snapshot {
+                break;
+            }
+            ticks += 1;
+            if ticks == power {
+                snapshot.clone_from(&guards);
+                power *= 2;
+                ticks = 0;
             }
         }
 
-        false
+        reports
+    }
+
+    /// Draw the cells visited by one guard as `X` and the cells visited by several guards
+    /// as `+`. Guards are drawn where they started.
+    fn render_patrol(&self, coverage: &HashMap<Position, usize>) -> String {
+        let mut output = String::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let position = (x, y);
+                output.push(
+                    if let Some((_, direction)) =
+                        self.guards.iter().find(|(start, _)| *start == position)
+                    {
+                        direction.to_guard()
+                    } else if self.obstacles.contains(&position) {
+                        '#'
+                    } else {
+                        match coverage.get(&position) {
+                            Some(1) => 'X',
+                            Some(_) => '+',
+                            None => '.',
+                        }
+                    },
+                );
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+}
+
+/// Cells as `x,y` in reading order.
+fn format_cells(cells: impl Iterator<Item = Position>) -> String {
+    let mut cells: Vec<Position> = cells.collect();
+    cells.sort_by_key(|(x, y)| (*y, *x));
+
+    cells
+        .iter()
+        .map(|(x, y)| format!("{x},{y}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn day6_patrol(input: String, turn_rule: TurnRule, collision: Collision) -> String {
+    let map = phase("Map::parse_input", || Map::parse_input(input));
+
+    let reports = phase("Map::patrol", || map.patrol(turn_rule, collision));
+
+    let mut output = String::new();
+    let mut coverage: HashMap<Position, usize> = HashMap::new();
+    for (i, report) in reports.iter().enumerate() {
+        let outcome = match report.outcome {
+            Outcome::Exited((x, y)) => format!("left the map at {x},{y}"),
+            Outcome::Cycle => "walks in a cycle".to_string(),
+            Outcome::Stopped((x, y)) => format!("stopped at {x},{y}"),
+        };
+        writeln!(
+            output,
+            "Guard {} from {},{} facing {:?}: visited {} cells, {outcome}",
+            i + 1,
+            report.start.0,
+            report.start.1,
+            report.direction,
+            report.visited.len()
+        )
+        .unwrap();
+        writeln!(output, "  {}", format_cells(report.visited.iter().copied())).unwrap();
+        for position in &report.visited {
+            *coverage.entry(*position).or_default() += 1;
+        }
+    }
+    let shared: Vec<Position> = coverage
+        .iter()
+        .filter(|(_, count)| **count > 1)
+        .map(|(position, _)| *position)
+        .collect();
+    writeln!(output, "Cells covered by multiple guards: {}", shared.len()).unwrap();
+    if !shared.is_empty() {
+        writeln!(output, "  {}", format_cells(shared.into_iter())).unwrap();
+    }
+    output += &map.render_patrol(&coverage);
+
+    output
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LoopAnalysis {
+    /// Steps before the guard enters the loop, turning in place counts as a step
+    prefix_length: usize,
+    cycle_length: usize,
+    /// Cells in which the guard turns inside the loop with its new direction, in walking order
+    turning_points: Vec<(Position, Direction)>,
+    /// Top left and bottom right corner of the loop
+    bounds: (Position, Position),
+    /// All states of the loop in walking order
+    cycle: Vec<(Position, Direction)>,
+}
+
+impl Map {
+    /// Walk from the start with an optional additional obstacle and describe the loop
+    /// the guard is caught in, or `None` if it leaves the map.
+    fn analyze_loop(&self, obstruction: Option<Position>) -> Option<LoopAnalysis> {
+        let mut position = self.guard_start_position;
+        let mut direction = self.guard_start_direction;
+        let mut states = vec![(position, direction)];
+        let mut seen: HashMap<(Position, Direction), usize> = HashMap::from([(states[0], 0)]);
+
+        let loop_start = loop {
+            let next = position + direction;
+            if !self.check_bounds(next) {
+                return None;
+            } else if self.obstacles.contains(&next) || obstruction == Some(next) {
+                direction = direction.rotate();
+            } else {
+                position = next;
+            }
+
+            if let Some(index) = seen.get(&(position, direction)) {
+                break *index;
+            }
+            seen.insert((position, direction), states.len());
+            states.push((position, direction));
+        };
+
+        let cycle = states.split_off(loop_start);
+        let turning_points = (0..cycle.len())
+            .filter(|i| cycle[(i + cycle.len() - 1) % cycle.len()].1 != cycle[*i].1)
+            .map(|i| cycle[i])
+            .collect();
+        let xs = cycle.iter().map(|((x, _), _)| *x);
+        let ys = cycle.iter().map(|((_, y), _)| *y);
+        let bounds = (
+            (xs.clone().min().unwrap(), ys.clone().min().unwrap()),
+            (xs.max().unwrap(), ys.max().unwrap()),
+        );
+
+        Some(LoopAnalysis {
+            prefix_length: loop_start,
+            cycle_length: cycle.len(),
+            turning_points,
+            bounds,
+            cycle,
+        })
+    }
+
+    /// Draw the loop on the map with `|` and `-` for the walking direction, `+` where
+    /// the guard turns or crosses its own path and `O` for the added obstacle.
+    fn render_loop(&self, analysis: &LoopAnalysis, obstruction: Option<Position>) -> String {
+        let mut vertical = HashSet::new();
+        let mut horizontal = HashSet::new();
+        for (position, direction) in &analysis.cycle {
+            match direction {
+                Direction::North | Direction::South => vertical.insert(*position),
+                Direction::East | Direction::West => horizontal.insert(*position),
+            };
+        }
+
+        let mut output = String::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let position = (x, y);
+                output.push(if obstruction == Some(position) {
+                    'O'
+                } else if self.obstacles.contains(&position) {
+                    '#'
+                } else if self.guard_start_position == position {
+                    self.guard_start_direction.to_guard()
+                } else {
+                    match (vertical.contains(&position), horizontal.contains(&position)) {
+                        (true, true) => '+',
+                        (true, false) => '|',
+                        (false, true) => '-',
+                        (false, false) => '.',
+                    }
+                });
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+}
+
+pub fn parse_position(value: &str) -> Result<Position, String> {
+    let (x, y) = value
+        .split_once(',')
+        .ok_or_else(|| format!("Expected a position like 3,6, found {value:?}"))?;
+    let parse = |n: &str| n.trim().parse::<isize>().map_err(|e| e.to_string());
+
+    Ok((parse(x)?, parse(y)?))
+}
+
+pub fn day6_loop(input: String, obstruction: Option<Position>) -> Result<String, String> {
+    let map = phase("Map::parse_input", || Map::parse_input(input));
+
+    if let Some((x, y)) = obstruction {
+        if (x, y) == map.guard_start_position {
+            return Err(format!(
+                "Can not place an obstruction on the guard at {x},{y}"
+            ));
+        }
+        if map.obstacles.contains(&(x, y)) {
+            return Err(format!("There already is an obstacle at {x},{y}"));
+        }
     }
+
+    let Some(analysis) = phase("Map::analyze_loop", || map.analyze_loop(obstruction)) else {
+        return Ok("The guard leaves the map".to_string());
+    };
+
+    let turning_points = analysis
+        .turning_points
+        .iter()
+        .map(|((x, y), direction)| format!("{x},{y} {direction:?}"))
+        .collect::<Vec<_>>()
+        .join(" -> ");
+    let ((left, top), (right, bottom)) = analysis.bounds;
+
+    let mut output = String::new();
+    writeln!(output, "Prefix length: {}", analysis.prefix_length).unwrap();
+    writeln!(output, "Cycle length: {}", analysis.cycle_length).unwrap();
+    writeln!(output, "Turning points: {turning_points}").unwrap();
+    writeln!(output, "Bounds: {left},{top} to {right},{bottom}").unwrap();
+    output += &map.render_loop(&analysis, obstruction);
+
+    Ok(output)
 }
 
 pub fn day6_part1(input: String) -> usize {
-    let mut map = Map::parse_input(input);
+    let mut map = phase("Map::parse_input", || Map::parse_input(input));
 
-    map.simulate_all_steps();
-    map.render();
+    phase("Map::simulate_all_steps", || map.simulate_all_steps());
+    print!("{}", phase("Map::render", || map.render()));
 
     map.visited.len()
 }
 pub fn day6_part2(input: String) -> usize {
-    let map = Map::parse_input(input);
+    let mut map = phase("Map::parse_input", || Map::parse_input(input));
 
-    map.check_for_loops()
+    phase("Map::simulate_all_steps", || map.simulate_all_steps());
+    let loops = phase("Map::check_for_loops", || map.check_for_loops());
+    info!("Obstacles causing a loop: {loops:?}");
+
+    loops.len()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::day6::{day6_part1, day6_part2};
+    use crate::{
+        day6::{
+            day6_loop, day6_part1, day6_part2, day6_patrol, parse_position, Collision, Direction,
+            JumpTable, Map, Outcome, TurnRule,
+        },
+        snapshot::assert_snapshot,
+    };
 
     #[test]
     fn test_part1() {
@@ -263,6 +776,202 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_part1_crlf_trailing_newline() {
+        assert_eq!(
+            41,
+            day6_part1(
+                "....#.....\r\n.........#\r\n..........\r\n..#.......\r\n.......#..\r\n..........\r\n.#..^.....\r\n........#.\r\n#.........\r\n......#...\r\n"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            41,
+            day6_part1(
+                "....#.....\n.........#\n..........\n..#.......\n.......#..\n..........\n.#..^.....\n........#.\n#.........\n......#...\n   \n"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_visited() {
+        let mut map = Map::parse_input(
+            r#"....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#..."#
+                .to_string(),
+        );
+        map.simulate_all_steps();
+
+        assert_snapshot("day6_visited", &map.render());
+    }
+
+    #[test]
+    fn test_jump_table() {
+        let mut map = Map::parse_input(
+            r#"....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#..."#
+                .to_string(),
+        );
+        assert_eq!(Some((4, 1)), map.jumps.jump((4, 6), Direction::North));
+        assert_eq!(Some((2, 6)), map.jumps.jump((4, 6), Direction::West));
+        assert_eq!(None, map.jumps.jump((4, 6), Direction::South));
+
+        map.jumps.add_obstacle((4, 3));
+        assert_eq!(Some((4, 4)), map.jumps.jump((4, 6), Direction::North));
+        assert_eq!(Some((4, 2)), map.jumps.jump((4, 1), Direction::South));
+        assert_eq!(Some((5, 3)), map.jumps.jump((8, 3), Direction::West));
+
+        map.jumps.remove_obstacle((4, 3));
+        let fresh = JumpTable::new(map.width, map.height, &map.obstacles);
+        assert_eq!(fresh.stops, map.jumps.stops);
+    }
+
+    #[test]
+    fn test_loop_positions() {
+        let mut map = Map::parse_input(
+            r#"....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#..."#
+                .to_string(),
+        );
+        map.simulate_all_steps();
+
+        assert_eq!(
+            vec![(3, 6), (6, 7), (7, 7), (1, 8), (3, 8), (7, 9)],
+            map.check_for_loops()
+        );
+    }
+
+    #[test]
+    fn test_patrol() {
+        let input = r#"....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#..."#;
+        let map = Map::parse_input(input.to_string());
+        let reports = map.patrol(TurnRule::Right, Collision::Ignore);
+        assert_eq!(Outcome::Exited((7, 9)), reports[0].outcome);
+        assert_eq!(41, reports[0].visited.len());
+
+        let map = Map::parse_input(".#..\n...#\n#^..\n..#.".to_string());
+        let reports = map.patrol(TurnRule::Left, Collision::Ignore);
+        assert_eq!(Outcome::Exited((0, 1)), reports[0].outcome);
+        let reports = map.patrol(TurnRule::Right, Collision::Ignore);
+        assert_eq!(Outcome::Cycle, reports[0].outcome);
+
+        let map = Map::parse_input(".>..<.".to_string());
+        let reports = map.patrol(TurnRule::Reverse, Collision::Stop);
+        assert_eq!(Outcome::Stopped((2, 0)), reports[0].outcome);
+        assert_eq!(Outcome::Stopped((3, 0)), reports[1].outcome);
+        let reports = map.patrol(TurnRule::Reverse, Collision::Block);
+        assert_eq!(Outcome::Exited((0, 0)), reports[0].outcome);
+        assert_eq!(Outcome::Exited((5, 0)), reports[1].outcome);
+
+        // Both guards bounce back and forth with different periods
+        let map = Map::parse_input("#>.#..\n#<...#".to_string());
+        let reports = map.patrol(TurnRule::Reverse, Collision::Block);
+        assert_eq!(Outcome::Cycle, reports[0].outcome);
+        assert_eq!(Outcome::Cycle, reports[1].outcome);
+        assert_eq!(2, reports[0].visited.len());
+        assert_eq!(4, reports[1].visited.len());
+
+        assert_eq!(
+            "Guard 1 from 1,0 facing East: visited 5 cells, left the map at 5,0
+  1,0 2,0 3,0 4,0 5,0
+Guard 2 from 4,0 facing West: visited 5 cells, left the map at 0,0
+  0,0 1,0 2,0 3,0 4,0
+Cells covered by multiple guards: 4
+  1,0 2,0 3,0 4,0
+X>++<X
+",
+            day6_patrol(".>..<.".to_string(), TurnRule::Right, Collision::Ignore)
+        );
+    }
+
+    #[test]
+    fn test_loop_analysis() {
+        let input = r#"....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#..."#;
+        let map = Map::parse_input(input.to_string());
+        assert_eq!(None, map.analyze_loop(None));
+
+        let analysis = map.analyze_loop(Some((3, 6))).unwrap();
+        assert_eq!(
+            vec![
+                ((4, 6), Direction::North),
+                ((4, 1), Direction::East),
+                ((8, 1), Direction::South),
+                ((8, 6), Direction::West),
+            ],
+            analysis.turning_points
+        );
+        assert_eq!(((4, 1), (8, 6)), analysis.bounds);
+        assert_eq!(0, analysis.prefix_length);
+        assert_eq!(22, analysis.cycle_length);
+
+        let analysis = map.analyze_loop(Some((7, 9))).unwrap();
+        assert_eq!(37, analysis.prefix_length);
+        assert_eq!(18, analysis.cycle_length);
+        assert_eq!(((1, 7), (7, 8)), analysis.bounds);
+
+        assert_eq!(Ok((3, 6)), parse_position("3,6"));
+        assert!(parse_position("3").is_err());
+        assert_snapshot(
+            "day6_loop",
+            &day6_loop(input.to_string(), Some((3, 6))).unwrap(),
+        );
+        assert_eq!(
+            Err("Can not place an obstruction on the guard at 4,6".to_string()),
+            day6_loop(input.to_string(), Some((4, 6)))
+        );
+        assert_eq!(
+            Err("There already is an obstacle at 4,0".to_string()),
+            day6_loop(input.to_string(), Some((4, 0)))
+        );
+
+        let map = Map::parse_input(".#..\n.>.#\n#...\n..#.".to_string());
+        let analysis = map.analyze_loop(None).unwrap();
+        assert_eq!(".#..\n.>+#\n#++.\n..#.\n", map.render_loop(&analysis, None));
+    }
+
     #[test]
     fn test_part2() {
         assert_eq!(
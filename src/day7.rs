@@ -1,16 +1,260 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display, Write},
+    iter::repeat_n,
+};
+
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use crate::{profile::phase, util::normalize_input};
+
+/// How to undo an operator given its result and right operand.
+#[derive(Debug, PartialEq, Eq)]
+enum Inverse {
+    /// No left operand produces the result
+    Unsolvable,
+    Left(usize),
+    /// Every left operand produces the result
+    Any,
+    /// The operator can not be undone, the left operand has to be searched for
+    Unknown,
+}
+
+/// A binary operator of the calibration equations, evaluated left to right.
+trait Operator: Sync {
+    fn symbol(&self) -> &'static str;
+
+    /// `None` if the result overflows or is not defined for the operands.
+    fn apply(&self, left: usize, right: usize) -> Option<usize>;
+
+    /// Find the left operand for which the operator gives `target`.
+    fn invert(&self, _target: usize, _right: usize) -> Inverse {
+        Inverse::Unknown
+    }
+
+    /// Binding strength when evaluating with operator precedence.
+    fn precedence(&self) -> u8;
+}
+
+impl Debug for dyn Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+fn left_or_unsolvable(left: Option<usize>) -> Inverse {
+    left.map_or(Inverse::Unsolvable, Inverse::Left)
+}
+
+struct Add;
+
+impl Operator for Add {
+    fn symbol(&self) -> &'static str {
+        "+"
+    }
+
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    fn apply(&self, left: usize, right: usize) -> Option<usize> {
+        left.checked_add(right)
+    }
+
+    fn invert(&self, target: usize, right: usize) -> Inverse {
+        left_or_unsolvable(target.checked_sub(right))
+    }
+}
+
+struct Multiply;
+
+impl Operator for Multiply {
+    fn symbol(&self) -> &'static str {
+        "*"
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn apply(&self, left: usize, right: usize) -> Option<usize> {
+        left.checked_mul(right)
+    }
+
+    fn invert(&self, target: usize, right: usize) -> Inverse {
+        match right {
+            0 if target == 0 => Inverse::Any,
+            0 => Inverse::Unsolvable,
+            _ if target.is_multiple_of(right) => Inverse::Left(target / right),
+            _ => Inverse::Unsolvable,
+        }
+    }
+}
+
+struct Concat;
+
+impl Operator for Concat {
+    fn symbol(&self) -> &'static str {
+        "||"
+    }
+
+    fn precedence(&self) -> u8 {
+        0
+    }
+
+    fn apply(&self, left: usize, right: usize) -> Option<usize> {
+        match concat_factor(right) {
+            Some(factor) => left.checked_mul(factor)?.checked_add(right),
+            None => (left == 0).then_some(right),
+        }
+    }
+
+    fn invert(&self, target: usize, right: usize) -> Inverse {
+        match concat_factor(right) {
+            Some(factor) if target % factor == right => Inverse::Left(target / factor),
+            // Only a zero prefix can be concatenated to such a large number
+            None if target == right => Inverse::Left(0),
+            _ => Inverse::Unsolvable,
+        }
+    }
+}
+
+/// Subtraction which is undefined for negative results.
+struct Subtract;
+
+impl Operator for Subtract {
+    fn symbol(&self) -> &'static str {
+        "-"
+    }
+
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    fn apply(&self, left: usize, right: usize) -> Option<usize> {
+        left.checked_sub(right)
+    }
+
+    fn invert(&self, target: usize, right: usize) -> Inverse {
+        left_or_unsolvable(target.checked_add(right))
+    }
+}
+
+/// Exact division which is undefined if there is a remainder.
+struct Divide;
+
+impl Operator for Divide {
+    fn symbol(&self) -> &'static str {
+        "/"
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn apply(&self, left: usize, right: usize) -> Option<usize> {
+        (right != 0 && left.is_multiple_of(right)).then(|| left / right)
+    }
+
+    fn invert(&self, target: usize, right: usize) -> Inverse {
+        match right {
+            0 => Inverse::Unsolvable,
+            _ => left_or_unsolvable(target.checked_mul(right)),
+        }
+    }
+}
+
+struct Power;
+
+impl Operator for Power {
+    fn symbol(&self) -> &'static str {
+        "^"
+    }
+
+    fn precedence(&self) -> u8 {
+        3
+    }
+
+    fn apply(&self, left: usize, right: usize) -> Option<usize> {
+        left.checked_pow(right.try_into().ok()?)
+    }
+}
+
+struct Remainder;
+
+impl Operator for Remainder {
+    fn symbol(&self) -> &'static str {
+        "%"
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn apply(&self, left: usize, right: usize) -> Option<usize> {
+        left.checked_rem(right)
+    }
+}
+
+static ADD: Add = Add;
+static MULTIPLY: Multiply = Multiply;
+static CONCAT: Concat = Concat;
+static SUBTRACT: Subtract = Subtract;
+static DIVIDE: Divide = Divide;
+static POWER: Power = Power;
+static REMAINDER: Remainder = Remainder;
+
+/// The operators which may be placed between the numbers of a calibration.
 #[derive(Clone, Debug)]
-enum Operator {
-    Add,
-    Multiply,
-    Concat,
+pub struct OperatorSet(Vec<&'static dyn Operator>);
+
+impl OperatorSet {
+    fn part1() -> Self {
+        Self(vec![&ADD, &MULTIPLY])
+    }
+
+    fn part2() -> Self {
+        Self(vec![&ADD, &MULTIPLY, &CONCAT])
+    }
+}
+
+/// Parse a comma separated list of operator symbols like `+,*,||`.
+pub fn parse_operators(value: &str) -> Result<OperatorSet, String> {
+    let all: [&'static dyn Operator; 7] = [
+        &ADD, &MULTIPLY, &CONCAT, &SUBTRACT, &DIVIDE, &POWER, &REMAINDER,
+    ];
+
+    let mut operators: Vec<&'static dyn Operator> = Vec::new();
+    for symbol in value.split(',').map(str::trim) {
+        let operator = all
+            .iter()
+            .find(|operator| operator.symbol() == symbol)
+            .copied()
+            .ok_or_else(|| format!("Unknown operator {symbol:?}"))?;
+        // Listing an operator twice would count every assignment using it twice
+        if operators.iter().any(|other| other.symbol() == symbol) {
+            return Err(format!("Operator {symbol:?} is listed more than once"));
+        }
+        operators.push(operator);
+    }
+
+    Ok(OperatorSet(operators))
+}
+
+/// How the operators of an equation are evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Evaluation {
+    /// Strictly left to right as in the puzzle
+    LeftToRight,
+    /// `^` before `*`, `/` and `%` before `+` and `-` before `||`, each left associative
+    Precedence,
 }
 
 struct Calibration {
     result: usize,
     numbers: Vec<usize>,
-    operators: Option<Vec<Operator>>,
-
-    count_of_one: usize,
+    operators: Option<Vec<&'static dyn Operator>>,
 
     solvable: Option<bool>,
 }
@@ -20,137 +264,365 @@ impl Calibration {
         let parts: Vec<&str> = input.split(":").collect();
         let result: usize = parts[0].parse().unwrap();
         let numbers: Vec<usize> = parts[1]
-            .trim()
-            .split(" ")
-            .map(|n| n.trim().parse().unwrap())
+            .split_whitespace()
+            .map(|n| n.parse().unwrap())
             .collect();
 
-        let count_of_one = numbers.clone().into_iter().filter(|n| *n == 1).count();
-
         Self {
             result,
             numbers,
-            count_of_one,
             operators: None,
             solvable: None,
         }
     }
 
-    fn calculate_result(&self, operators: &[Operator]) -> usize {
+    /// Evaluate left to right, `None` on overflow or undefined operations.
+    fn calculate_result(&self, operators: &[&dyn Operator]) -> Option<usize> {
         let mut result = self.numbers[0];
-        for (index, num) in self.numbers.clone().into_iter().skip(1).enumerate() {
-            let operator = &operators[index];
-            match operator {
-                Operator::Add => {
-                    result += num;
-                }
-                Operator::Multiply => {
-                    result *= num;
-                }
-                Operator::Concat => {
-                    result = 10_usize.pow((num as f64).log10().floor() as u32 + 1) * result + num;
+        for (operator, num) in operators.iter().zip(&self.numbers[1..]) {
+            result = operator.apply(result, *num)?;
+        }
+
+        Some(result)
+    }
+
+    /// Evaluate with operator precedence, `None` on overflow or undefined operations.
+    fn calculate_with_precedence(&self, operators: &[&dyn Operator]) -> Option<usize> {
+        fn reduce(values: &mut Vec<usize>, operator: &dyn Operator) -> Option<()> {
+            let right = values.pop().unwrap();
+            let left = values.pop().unwrap();
+            values.push(operator.apply(left, right)?);
+            Some(())
+        }
+
+        let mut values = vec![self.numbers[0]];
+        let mut pending: Vec<&dyn Operator> = Vec::new();
+        for (operator, num) in operators.iter().zip(&self.numbers[1..]) {
+            while let Some(previous) = pending.pop_if(|p| p.precedence() >= operator.precedence()) {
+                reduce(&mut values, previous)?;
+            }
+            pending.push(*operator);
+            values.push(*num);
+        }
+        while let Some(operator) = pending.pop() {
+            reduce(&mut values, operator)?;
+        }
+
+        values.pop()
+    }
+
+    fn evaluate(&self, operators: &[&dyn Operator], evaluation: Evaluation) -> Option<usize> {
+        match evaluation {
+            Evaluation::LeftToRight => self.calculate_result(operators),
+            Evaluation::Precedence => self.calculate_with_precedence(operators),
+        }
+    }
+
+    /// Lazily enumerate every operator assignment which gives the result.
+    fn solutions<'a>(
+        &'a self,
+        operators: &'a OperatorSet,
+        evaluation: Evaluation,
+    ) -> impl Iterator<Item = Vec<&'static dyn Operator>> + 'a {
+        let assignments = (self.numbers.len() > 1)
+            .then(|| {
+                repeat_n(operators.0.iter().copied(), self.numbers.len() - 1)
+                    .multi_cartesian_product()
+            })
+            .into_iter()
+            .flatten();
+
+        // A single number needs no operators
+        let single = (self.numbers.len() == 1).then(Vec::new);
+        single
+            .into_iter()
+            .chain(assignments)
+            .filter(move |assignment| self.evaluate(assignment, evaluation) == Some(self.result))
+    }
+
+    /// Number of operator assignments which give the result. Left to right evaluation
+    /// is counted backwards from the result instead of trying every assignment.
+    fn count_solutions(&self, operators: &OperatorSet, evaluation: Evaluation) -> usize {
+        match evaluation {
+            Evaluation::LeftToRight => {
+                self.count_backwards(operators, self.numbers.len(), self.result)
+            }
+            Evaluation::Precedence => self.count_with_precedence(operators),
+        }
+    }
+
+    /// Evaluate all assignments with operator precedence at once from the left. Operators
+    /// waiting for an operator which binds stronger are kept on a stack together with their
+    /// left operand, and assignments which lead to the same stack and value are counted
+    /// together instead of being evaluated one by one.
+    fn count_with_precedence(&self, operators: &OperatorSet) -> usize {
+        // Pending (left operand, operator index) pairs and the value right of them
+        type State = (Vec<(usize, usize)>, usize);
+
+        let mut states: HashMap<State, usize> = HashMap::from([((Vec::new(), self.numbers[0]), 1)]);
+        for number in &self.numbers[1..] {
+            let mut next: HashMap<State, usize> = HashMap::new();
+            for ((pending, value), count) in states {
+                for (index, operator) in operators.0.iter().enumerate() {
+                    let Some((mut pending, left)) =
+                        reduce_pending(operators, pending.clone(), value, operator.precedence())
+                    else {
+                        continue;
+                    };
+                    pending.push((left, index));
+                    *next.entry((pending, *number)).or_default() += count;
                 }
             }
+            states = next;
         }
 
-        result
+        states
+            .into_iter()
+            .filter(|((pending, value), _)| {
+                reduce_pending(operators, pending.clone(), *value, 0)
+                    .is_some_and(|(_, value)| value == self.result)
+            })
+            .map(|(_, count)| count)
+            .sum()
     }
 
-    fn solve(&mut self, allow_concat: bool) {
-        let mut operators = vec![Operator::Add; self.numbers.len() - 1];
-        operators.fill(Operator::Add);
+    fn count_backwards(&self, operators: &OperatorSet, count: usize, target: usize) -> usize {
+        let last = self.numbers[count - 1];
+        if count == 1 {
+            return usize::from(last == target);
+        }
 
-        let result = self.solve_recursive(allow_concat, operators, 0);
+        operators
+            .0
+            .iter()
+            .map(|operator| match operator.invert(target, last) {
+                Inverse::Unsolvable => 0,
+                Inverse::Left(remaining) => self.count_backwards(operators, count - 1, remaining),
+                Inverse::Any => {
+                    self.count_forward(operators, count - 1, 1, self.numbers[0], &|_| true)
+                }
+                Inverse::Unknown => {
+                    self.count_forward(operators, count - 1, 1, self.numbers[0], &|value| {
+                        operator.apply(value, last) == Some(target)
+                    })
+                }
+            })
+            .sum()
+    }
+
+    fn count_forward(
+        &self,
+        operators: &OperatorSet,
+        count: usize,
+        used: usize,
+        value: usize,
+        accept: &dyn Fn(usize) -> bool,
+    ) -> usize {
+        if used == count {
+            return usize::from(accept(value));
+        }
+
+        operators
+            .0
+            .iter()
+            .filter_map(|operator| operator.apply(value, self.numbers[used]))
+            .map(|next| self.count_forward(operators, count, used + 1, next, accept))
+            .sum()
+    }
+
+    fn solve(&mut self, operators: &OperatorSet) {
+        let result = self.solve_backwards(operators, self.numbers.len(), self.result);
+        if let Some(operators) = &result {
+            debug_assert_eq!(Some(self.result), self.calculate_result(operators));
+        }
         self.solvable = Some(result.is_some());
         self.operators = result;
     }
 
-    fn solve_recursive(
+    /// Peel numbers off the end of the first `count` numbers, undoing the operator that
+    /// would have combined them with `target`. Branches whose inverse does not exist
+    /// (negative difference, remainder, missing suffix) are pruned right away.
+    fn solve_backwards(
         &self,
-        allow_concat: bool,
-        operators: Vec<Operator>,
-        pos: usize,
-    ) -> Option<Vec<Operator>> {
-        let current = self.calculate_result(&operators);
-        if current == self.result {
-            return Some(operators);
-        }
-        if current > self.result + self.count_of_one {
-            return None;
-        }
-        for i in pos..operators.len() {
-            let mut new_operators = operators.clone();
-            new_operators[i] = Operator::Multiply;
-
-            let recursive_result = self.solve_recursive(allow_concat, new_operators.clone(), i + 1);
-            if recursive_result.is_some() {
-                return recursive_result;
-            }
-
-            if allow_concat {
-                new_operators[i] = Operator::Concat;
+        operators: &OperatorSet,
+        count: usize,
+        target: usize,
+    ) -> Option<Vec<&'static dyn Operator>> {
+        let last = self.numbers[count - 1];
+        if count == 1 {
+            return (last == target).then(Vec::new);
+        }
 
-                let recursive_result = self.solve_recursive(allow_concat, new_operators, i + 1);
-                if recursive_result.is_some() {
-                    return recursive_result;
+        operators.0.iter().find_map(|operator| {
+            let mut found = match operator.invert(target, last) {
+                Inverse::Unsolvable => None,
+                Inverse::Left(remaining) => self.solve_backwards(operators, count - 1, remaining),
+                Inverse::Any => {
+                    self.solve_forward(operators, count - 1, 1, self.numbers[0], &|_| true)
                 }
-            }
+                Inverse::Unknown => {
+                    self.solve_forward(operators, count - 1, 1, self.numbers[0], &|value| {
+                        operator.apply(value, last) == Some(target)
+                    })
+                }
+            }?;
+            found.push(*operator);
+            Some(found)
+        })
+    }
+
+    /// Try all operators from the front until the first `count` numbers are used up
+    /// and `accept` the value. Operations which overflow or are undefined end the branch.
+    fn solve_forward(
+        &self,
+        operators: &OperatorSet,
+        count: usize,
+        used: usize,
+        value: usize,
+        accept: &dyn Fn(usize) -> bool,
+    ) -> Option<Vec<&'static dyn Operator>> {
+        if used == count {
+            return accept(value).then(Vec::new);
         }
 
-        None
+        operators.0.iter().find_map(|operator| {
+            let next = operator.apply(value, self.numbers[used])?;
+            let mut found = self.solve_forward(operators, count, used + 1, next, accept)?;
+            found.insert(0, *operator);
+            Some(found)
+        })
+    }
+}
+
+/// Apply the pending operators which bind at least as strong as `precedence` to `value`,
+/// `None` on overflow or undefined operations.
+fn reduce_pending(
+    operators: &OperatorSet,
+    mut pending: Vec<(usize, usize)>,
+    mut value: usize,
+    precedence: u8,
+) -> Option<(Vec<(usize, usize)>, usize)> {
+    while let Some((left, index)) =
+        pending.pop_if(|(_, index)| operators.0[*index].precedence() >= precedence)
+    {
+        value = operators.0[index].apply(left, value)?;
+    }
+
+    Some((pending, value))
+}
+
+/// The power of ten shifting a number far enough left to append `number`,
+/// or `None` if it does not fit into `usize`.
+fn concat_factor(number: usize) -> Option<usize> {
+    let mut factor: usize = 10;
+    while factor <= number {
+        factor = factor.checked_mul(10)?;
     }
+
+    Some(factor)
 }
 
 fn parse_complete_input(input: String) -> Vec<Calibration> {
-    input
-        .trim()
-        .split("\n")
+    normalize_input(&input)
+        .lines()
         .map(Calibration::parse_input)
         .collect()
 }
 
-pub fn day7_part1(input: String) -> usize {
-    let mut calibrations = parse_complete_input(input);
+/// The sum of the solvable calibration results does not fit into `usize`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SumOverflow {
+    /// Line of the calibration whose result overflowed the sum, counting from 1
+    line: usize,
+}
+
+impl Display for SumOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The sum of the solvable results overflows at the calibration on line {}",
+            self.line
+        )
+    }
+}
 
-    let mut result = 0;
+/// Sum of the results of all calibrations which can be solved with the operators.
+pub fn day7_with_operators(input: String, operators: &OperatorSet) -> Result<usize, SumOverflow> {
+    let mut calibrations = phase("parse_complete_input", || parse_complete_input(input));
 
-    for calibration in calibrations.iter_mut() {
-        calibration.solve(false);
+    phase("Calibration::solve", || {
+        let mut result: usize = 0;
 
-        if calibration.solvable == Some(true) {
-            result += calibration.result;
+        for (i, calibration) in calibrations.iter_mut().enumerate() {
+            calibration.solve(operators);
+
+            if calibration.solvable == Some(true) {
+                result = result
+                    .checked_add(calibration.result)
+                    .ok_or(SumOverflow { line: i + 1 })?;
+            }
         }
-    }
 
-    result
+        Ok(result)
+    })
 }
 
-pub fn day7_part2(input: String) -> usize {
-    let mut calibrations = parse_complete_input(input);
-
-    let mut result = 0;
+/// Number of operator assignments solving each calibration, optionally followed by
+/// the solving equations.
+pub fn day7_counts(
+    input: String,
+    operators: &OperatorSet,
+    evaluation: Evaluation,
+    list: bool,
+) -> String {
+    let calibrations = phase("parse_complete_input", || parse_complete_input(input));
+
+    phase("Calibration::count_solutions", || {
+        let mut output = String::new();
+        for calibration in &calibrations {
+            writeln!(
+                output,
+                "{}: {}: {} solutions",
+                calibration.result,
+                calibration.numbers.iter().join(" "),
+                calibration.count_solutions(operators, evaluation)
+            )
+            .unwrap();
+            if list {
+                for solution in calibration.solutions(operators, evaluation) {
+                    let mut equation = calibration.numbers[0].to_string();
+                    for (operator, number) in solution.iter().zip(&calibration.numbers[1..]) {
+                        write!(equation, " {} {number}", operator.symbol()).unwrap();
+                    }
+                    writeln!(output, "  {equation}").unwrap();
+                }
+            }
+        }
 
-    for calibration in calibrations.iter_mut() {
-        calibration.solve(true);
+        output
+    })
+}
 
-        if calibration.solvable == Some(true) {
-            result += calibration.result;
-        }
-    }
+pub fn day7_part1(input: String) -> Result<usize, SumOverflow> {
+    day7_with_operators(input, &OperatorSet::part1())
+}
 
-    result
+pub fn day7_part2(input: String) -> Result<usize, SumOverflow> {
+    day7_with_operators(input, &OperatorSet::part2())
 }
 
 #[cfg(test)]
 mod test {
-    use crate::day7::{day7_part1, day7_part2};
+    use crate::day7::{
+        day7_counts, day7_part1, day7_part2, day7_with_operators, parse_operators, Evaluation,
+    };
 
-    use super::{Calibration, Operator};
+    use super::{Calibration, SumOverflow, CONCAT, MULTIPLY};
 
     #[test]
     fn test_part1() {
         assert_eq!(
-            3749,
+            Ok(3749),
             day7_part1(
                 r#"190: 10 19
 3267: 81 40 27
@@ -169,46 +641,217 @@ mod test {
     #[test]
     fn concat() {
         let test = Calibration {
-            count_of_one: 0,
             numbers: vec![123, 45],
-            operators: Some(vec![Operator::Concat]),
+            operators: Some(vec![&CONCAT]),
             result: 12345,
             solvable: Some(true),
         };
 
-        assert_eq!(12345, test.calculate_result(&[Operator::Concat]));
+        assert_eq!(Some(12345), test.calculate_result(&[&CONCAT]));
     }
 
     #[test]
     fn concat_div10() {
         let test = Calibration {
-            count_of_one: 0,
             numbers: vec![120, 45],
-            operators: Some(vec![Operator::Concat]),
+            operators: Some(vec![&CONCAT]),
             result: 12045,
             solvable: Some(true),
         };
 
-        assert_eq!(12045, test.calculate_result(&[Operator::Concat]));
+        assert_eq!(Some(12045), test.calculate_result(&[&CONCAT]));
     }
 
     #[test]
     fn concat_small() {
         let test = Calibration {
-            count_of_one: 0,
             numbers: vec![120, 1],
-            operators: Some(vec![Operator::Concat]),
+            operators: Some(vec![&CONCAT]),
             result: 12045,
             solvable: Some(true),
         };
 
-        assert_eq!(1201, test.calculate_result(&[Operator::Concat]));
+        assert_eq!(Some(1201), test.calculate_result(&[&CONCAT]));
+    }
+
+    fn symbols(test: &Calibration) -> Option<Vec<&'static str>> {
+        test.operators
+            .as_ref()
+            .map(|operators| operators.iter().map(|operator| operator.symbol()).collect())
+    }
+
+    #[test]
+    fn solve_backwards() {
+        let part1 = parse_operators("+,*").unwrap();
+        let part2 = parse_operators("+,*,||").unwrap();
+
+        let mut test = Calibration::parse_input("7290: 6 8 6 15");
+        test.solve(&part2);
+        assert_eq!(Some(vec!["*", "||", "*"]), symbols(&test));
+        assert_eq!(
+            Some(7290),
+            test.calculate_result(test.operators.as_ref().unwrap())
+        );
+
+        let mut test = Calibration::parse_input("0: 3 4 0");
+        test.solve(&part1);
+        assert_eq!(
+            Some(0),
+            test.calculate_result(test.operators.as_ref().unwrap())
+        );
+
+        let mut test = Calibration::parse_input("83: 17 5");
+        test.solve(&part2);
+        assert_eq!(Some(false), test.solvable);
+    }
+
+    #[test]
+    fn solve_large_numbers() {
+        let part1 = parse_operators("+,*").unwrap();
+        let part2 = parse_operators("+,*,||").unwrap();
+
+        let mut test = Calibration::parse_input("18446744073709551615: 1844674407370955161 5");
+        test.solve(&part2);
+        assert_eq!(Some(vec!["||"]), symbols(&test));
+
+        let mut test = Calibration::parse_input("18446744073709551614: 9223372036854775807 2");
+        test.solve(&part1);
+        assert_eq!(Some(vec!["*"]), symbols(&test));
+
+        let mut test = Calibration::parse_input("18446744073709551615: 0 18446744073709551615");
+        test.solve(&part2);
+        assert_eq!(Some(vec!["+"]), symbols(&test));
+
+        // 2^64 overflows instead of wrapping around to 0
+        let mut test = Calibration::parse_input("0: 2 64");
+        test.solve(&parse_operators("^").unwrap());
+        assert_eq!(Some(false), test.solvable);
+        let test = Calibration::parse_input("0: 4294967296 4294967296");
+        assert_eq!(None, test.calculate_result(&[&MULTIPLY]));
+    }
+
+    #[test]
+    fn custom_operators() {
+        let operators = parse_operators("-,/,^,%").unwrap();
+        let mut test = Calibration::parse_input("4: 20 5 2 7");
+        test.solve(&operators);
+        assert_eq!(Some(vec!["^", "-", "%"]), symbols(&test));
+
+        let mut test = Calibration::parse_input("11: 3 2 2");
+        test.solve(&parse_operators("^,+").unwrap());
+        assert_eq!(Some(vec!["^", "+"]), symbols(&test));
+
+        let mut test = Calibration::parse_input("0: 7 3 2");
+        test.solve(&parse_operators("-, %").unwrap());
+        assert_eq!(Some(vec!["-", "%"]), symbols(&test));
+
+        assert_eq!(
+            Ok(15),
+            day7_with_operators(
+                "15: 20 5\n3: 10 2\n".to_string(),
+                &parse_operators("-,/").unwrap()
+            )
+        );
+        assert!(parse_operators("+,x").is_err());
+        assert_eq!(
+            "Operator \"+\" is listed more than once",
+            parse_operators("+, *,+").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn sum_overflow() {
+        assert_eq!(
+            Err(SumOverflow { line: 3 }),
+            day7_part1("18446744073709551614: 9223372036854775807 2\n83: 17 5\n2: 2".to_string())
+        );
+    }
+
+    #[test]
+    fn count_solutions() {
+        let operators = parse_operators("+,*,||").unwrap();
+
+        let test = Calibration::parse_input("4: 2 2");
+        assert_eq!(2, test.count_solutions(&operators, Evaluation::LeftToRight));
+        let test = Calibration::parse_input("0: 5 0 3 0");
+        assert_eq!(
+            test.solutions(&operators, Evaluation::LeftToRight).count(),
+            test.count_solutions(&operators, Evaluation::LeftToRight)
+        );
+
+        let test = Calibration::parse_input("14: 2 3 4");
+        assert_eq!(0, test.count_solutions(&operators, Evaluation::LeftToRight));
+        let solutions: Vec<Vec<&str>> = test
+            .solutions(&operators, Evaluation::Precedence)
+            .map(|solution| solution.iter().map(|operator| operator.symbol()).collect())
+            .collect();
+        assert_eq!(vec![vec!["+", "*"]], solutions);
+
+        let test = Calibration::parse_input("7: 7");
+        assert_eq!(1, test.count_solutions(&operators, Evaluation::Precedence));
+
+        let operators = parse_operators("+,-,^,%").unwrap();
+        let test = Calibration::parse_input("2: 2 3 2 1");
+        assert_eq!(
+            test.solutions(&operators, Evaluation::LeftToRight).count(),
+            test.count_solutions(&operators, Evaluation::LeftToRight)
+        );
+        let test = Calibration::parse_input("11: 2 3 2");
+        let operators = parse_operators("+,^").unwrap();
+        let assignment = [operators.0[0], operators.0[1]];
+        assert_eq!(Some(25), test.calculate_result(&assignment));
+        assert_eq!(Some(11), test.calculate_with_precedence(&assignment));
+        assert_eq!(1, test.count_solutions(&operators, Evaluation::Precedence));
+
+        assert_eq!(
+            "3267: 81 40 27: 2 solutions\n83: 17 5: 0 solutions\n",
+            day7_counts(
+                "3267: 81 40 27\n83: 17 5".to_string(),
+                &parse_operators("+,*").unwrap(),
+                Evaluation::LeftToRight,
+                false
+            )
+        );
+        assert_eq!(
+            "3267: 81 40 27: 2 solutions\n  81 + 40 * 27\n  81 * 40 + 27\n190: 10 19: 1 solutions\n  10 * 19\n",
+            day7_counts(
+                "3267: 81 40 27\n190: 10 19".to_string(),
+                &parse_operators("+,*").unwrap(),
+                Evaluation::LeftToRight,
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn count_with_precedence() {
+        let operators = parse_operators("+,*,||,-,/,^,%").unwrap();
+        for input in [
+            "6: 1 2 3 1 2",
+            "24: 2 3 4 0 2 1",
+            "0: 5 0 3 0 1",
+            "23: 2 3 2 2 1 3",
+        ] {
+            let test = Calibration::parse_input(input);
+            assert_eq!(
+                test.solutions(&operators, Evaluation::Precedence).count(),
+                test.count_solutions(&operators, Evaluation::Precedence),
+                "{input}"
+            );
+        }
+
+        // Forty ones only add up to 40 if every operator is a +, out of 2^39 assignments
+        let test = Calibration::parse_input(&format!("40: {}", ["1"; 40].join(" ")));
+        assert_eq!(
+            1,
+            test.count_solutions(&parse_operators("+,*").unwrap(), Evaluation::Precedence)
+        );
     }
 
     #[test]
     fn test_part2() {
         assert_eq!(
-            11387,
+            Ok(11387),
             day7_part2(
                 r#"190: 10 19
 3267: 81 40 27
@@ -1,10 +1,15 @@
 use std::{
-    collections::{HashMap, HashSet},
-    ops::{Add, Mul, Sub},
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    iter::successors,
+    ops::{Add, Mul, Neg, RangeInclusive, Sub},
 };
 
+use clap::ValueEnum;
 use itertools::Itertools;
 
+use crate::{profile::phase, util::normalize_input};
+
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 struct Position {
     x: i32,
@@ -44,6 +49,60 @@ impl Add for Position {
     }
 }
 
+impl Neg for Position {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        -1 * self
+    }
+}
+
+impl Position {
+    /// `self + rhs`, or `None` if a coordinate overflows.
+    fn checked_add(self, rhs: Position) -> Option<Position> {
+        Some(Position {
+            x: self.x.checked_add(rhs.x)?,
+            y: self.y.checked_add(rhs.y)?,
+        })
+    }
+
+    /// `self - rhs`, or `None` if a coordinate overflows.
+    fn checked_sub(self, rhs: Position) -> Option<Position> {
+        Some(Position {
+            x: self.x.checked_sub(rhs.x)?,
+            y: self.y.checked_sub(rhs.y)?,
+        })
+    }
+
+    /// The smallest step in the same direction which still lands on grid points.
+    fn reduced(self) -> Position {
+        let divisor = gcd(self.x, self.y);
+        Position {
+            x: self.x / divisor,
+            y: self.y / divisor,
+        }
+    }
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Where antinodes appear on the line through two antennas of the same frequency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Resonance {
+    /// Part 1: one antinode beyond each antenna at their distance
+    Single,
+    /// Part 2 as the puzzle intends it: repeat the distance between the antennas
+    Puzzle,
+    /// Every grid point on the line through both antennas
+    Collinear,
+}
+
 #[derive(Debug)]
 struct Map {
     antennas: HashMap<char, HashSet<Position>>,
@@ -55,8 +114,9 @@ struct Map {
 
 impl Map {
     fn parse_input(input: String) -> Self {
+        let input = normalize_input(&input);
         let mut antennas: HashMap<char, HashSet<Position>> = HashMap::new();
-        for (y, line) in input.split("\n").enumerate() {
+        for (y, line) in input.lines().enumerate() {
             for (x, char) in line.chars().enumerate() {
                 if char != '.' {
                     if let Some(positions) = antennas.get_mut(&char) {
@@ -81,70 +141,307 @@ impl Map {
             antennas,
             antinodes: HashSet::new(),
 
-            width: input.split("\n").nth(0).unwrap().chars().count() as i32,
-            height: input.trim().split("\n").count() as i32,
+            width: input.lines().nth(0).unwrap().chars().count() as i32,
+            height: input.lines().count() as i32,
         }
     }
 
-    fn place_antinodes(&mut self, repeating_pattern: bool) {
+    fn place_antinodes(&mut self, resonance: Resonance) {
+        let mut antinodes = HashSet::new();
         for antenna_positions in self.antennas.values() {
-            for antenna_pair in antenna_positions.iter().permutations(2) {
+            for antenna_pair in antenna_positions.iter().combinations(2) {
                 let antenna1 = *antenna_pair[0];
                 let antenna2 = *antenna_pair[1];
 
                 let distance = antenna2 - antenna1;
 
-                if repeating_pattern {
-                    for i in 0..self.width.max(self.height) {
-                        let antinode1 = antenna1 - (i * distance);
-                        let antinode2 = antenna2 + (i * distance);
+                match resonance {
+                    Resonance::Single => antinodes.extend(
+                        [antenna1 - distance, antenna2 + distance]
+                            .into_iter()
+                            .filter(|antinode| self.check_bounds(*antinode)),
+                    ),
+                    Resonance::Puzzle => {
+                        antinodes.extend(self.walk(antenna1, -distance));
+                        antinodes.extend(self.walk(antenna2, distance));
+                    }
+                    Resonance::Collinear => {
+                        let step = distance.reduced();
+                        antinodes.extend(self.walk(antenna1, -step));
+                        antinodes.extend(self.walk(antenna1, step));
+                    }
+                }
+            }
+        }
+
+        self.antinodes.extend(antinodes);
+    }
 
-                        if self.check_bounds(antinode1) {
-                            self.antinodes.insert(antinode1);
-                        }
-                        if self.check_bounds(antinode2) {
-                            self.antinodes.insert(antinode2);
+    /// The positions `start`, `start + step`, ... as long as they are on the map.
+    fn walk(&self, start: Position, step: Position) -> impl Iterator<Item = Position> + '_ {
+        successors(Some(start), move |position| Some(*position + step))
+            .take_while(|position| self.check_bounds(*position))
+    }
+
+    fn check_bounds(&self, position: Position) -> bool {
+        position.x >= 0 && position.x < self.width && position.y >= 0 && position.y < self.height
+    }
+}
+
+/// Which points on the line through two antennas A and B with `d = B - A` are antinodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Harmonics {
+    /// `A - k * d` and `B + k * d` for every k in the range, 0 being the antennas themselves
+    Multiples(RangeInclusive<u32>),
+    /// Points whose distances to the two antennas have the ratio p:q, both positive
+    Ratio(i32, i32),
+}
+
+/// Parse a harmonic range like `1..=3`, `1..4`, `0..` or `2`. As in Rust, `a..b`
+/// excludes `b`.
+pub fn parse_harmonics(value: &str) -> Result<Harmonics, String> {
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<u32>()
+            .map_err(|e| format!("{value:?}: {e}"))
+    };
+
+    let empty = || format!("The range {value:?} contains no multiples");
+
+    let range = match value.split_once("..") {
+        Some((start, "")) => parse(start)?..=u32::MAX,
+        Some((start, end)) => match end.strip_prefix('=') {
+            Some(end) => parse(start)?..=parse(end)?,
+            None => parse(start)?..=parse(end)?.checked_sub(1).ok_or_else(empty)?,
+        },
+        None => parse(value)?..=parse(value)?,
+    };
+    if range.is_empty() {
+        return Err(empty());
+    }
+
+    Ok(Harmonics::Multiples(range))
+}
+
+/// Parse a distance ratio like `1:3`.
+pub fn parse_ratio(value: &str) -> Result<Harmonics, String> {
+    let parse = |n: &str| {
+        let n = n
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| format!("{value:?}: {e}"))?;
+        i32::try_from(n).map_err(|e| format!("{value:?}: {e}"))
+    };
+
+    let (p, q) = value
+        .split_once(':')
+        .ok_or_else(|| format!("Expected a ratio like 1:2, found {value:?}"))?;
+    let (p, q) = (parse(p)?, parse(q)?);
+    if p == 0 || q == 0 {
+        return Err(format!(
+            "Both parts of the ratio {value:?} need to be positive"
+        ));
+    }
+
+    Ok(Harmonics::Ratio(p, q))
+}
+
+/// `numerator / denominator * position` if that is a grid point. Computed in `i64`
+/// so large ratios don't overflow.
+fn scale(position: Position, numerator: i64, denominator: i64) -> Option<Position> {
+    let (x, y) = (
+        i64::from(position.x) * numerator,
+        i64::from(position.y) * numerator,
+    );
+    if x % denominator != 0 || y % denominator != 0 {
+        return None;
+    }
+
+    Some(Position {
+        x: i32::try_from(x / denominator).ok()?,
+        y: i32::try_from(y / denominator).ok()?,
+    })
+}
+
+impl Map {
+    /// Antinodes of every frequency. With `between` points between the antennas count as
+    /// well: every grid point for multiples, the points with the ratio for ratios.
+    fn harmonic_antinodes(
+        &self,
+        harmonics: &Harmonics,
+        between: bool,
+    ) -> BTreeMap<char, HashSet<Position>> {
+        let mut antinodes: BTreeMap<char, HashSet<Position>> = BTreeMap::new();
+
+        for (frequency, antenna_positions) in &self.antennas {
+            let frequency_antinodes = antinodes.entry(*frequency).or_default();
+            for antenna_pair in antenna_positions.iter().combinations(2) {
+                let antenna1 = *antenna_pair[0];
+                let antenna2 = *antenna_pair[1];
+                let distance = antenna2 - antenna1;
+
+                match harmonics {
+                    Harmonics::Multiples(range) => {
+                        let skip = *range.start() as usize;
+                        let take = (*range.end() - *range.start()) as usize + 1;
+                        frequency_antinodes
+                            .extend(self.walk(antenna1, -distance).skip(skip).take(take));
+                        frequency_antinodes
+                            .extend(self.walk(antenna2, distance).skip(skip).take(take));
+                        if between {
+                            frequency_antinodes.extend(
+                                self.walk(antenna1, distance.reduced())
+                                    .skip(1)
+                                    .take_while(|position| *position != antenna2),
+                            );
                         }
                     }
-                } else {
-                    let antinode1 = antenna1 - distance;
-                    let antinode2 = antenna2 + distance;
+                    Harmonics::Ratio(p, q) => {
+                        let divisor = gcd(*p, *q);
+                        let (p, q) = (*p / divisor, *q / divisor);
+                        let (near, far) = (p.min(q), p.max(q));
 
-                    if self.check_bounds(antinode1) {
-                        self.antinodes.insert(antinode1);
-                    }
-                    if self.check_bounds(antinode2) {
-                        self.antinodes.insert(antinode2);
+                        // Points too far away to be represented are off the map anyway
+                        let mut candidates = Vec::new();
+                        if near != far {
+                            if let Some(offset) = scale(distance, near.into(), (far - near).into())
+                            {
+                                candidates.extend([
+                                    antenna1.checked_sub(offset),
+                                    antenna2.checked_add(offset),
+                                ]);
+                            }
+                        }
+                        if between {
+                            for part in [near, far] {
+                                if let Some(offset) =
+                                    scale(distance, part.into(), i64::from(near) + i64::from(far))
+                                {
+                                    candidates.push(antenna1.checked_add(offset));
+                                }
+                            }
+                        }
+                        frequency_antinodes.extend(
+                            candidates
+                                .into_iter()
+                                .flatten()
+                                .filter(|antinode| self.check_bounds(*antinode)),
+                        );
                     }
                 }
             }
         }
+
+        antinodes
     }
 
-    fn check_bounds(&self, position: Position) -> bool {
-        position.x >= 0 && position.x < self.width && position.y >= 0 && position.y < self.height
+    /// Antinodes drawn as `#` and as `*` where several frequencies overlap.
+    /// Antennas are drawn on top.
+    fn render_antinodes(&self, antinodes: &HashMap<Position, usize>) -> String {
+        let antennas: HashMap<Position, char> = self
+            .antennas
+            .iter()
+            .flat_map(|(frequency, positions)| {
+                positions.iter().map(|position| (*position, *frequency))
+            })
+            .collect();
+
+        let mut output = String::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let position = Position { x, y };
+                output.push(match (antennas.get(&position), antinodes.get(&position)) {
+                    (Some(frequency), _) => *frequency,
+                    (None, Some(1)) => '#',
+                    (None, Some(_)) => '*',
+                    (None, None) => '.',
+                });
+            }
+            output.push('\n');
+        }
+
+        output
     }
 }
 
-pub fn day8_part1(input: String) -> usize {
-    let mut map = Map::parse_input(input);
+pub fn day8_harmonics(input: String, harmonics: &Harmonics, between: bool) -> String {
+    let map = phase("Map::parse_input", || Map::parse_input(input));
 
-    map.place_antinodes(false);
+    let antinodes = phase("Map::harmonic_antinodes", || {
+        map.harmonic_antinodes(harmonics, between)
+    });
 
-    map.antinodes.len()
+    let mut frequencies_per_position: HashMap<Position, usize> = HashMap::new();
+    let mut output = String::new();
+    for (frequency, positions) in &antinodes {
+        writeln!(
+            output,
+            "Frequency {frequency}: {} antinodes",
+            positions.len()
+        )
+        .unwrap();
+        for position in positions {
+            *frequencies_per_position.entry(*position).or_default() += 1;
+        }
+    }
+
+    let overlapping = frequencies_per_position
+        .iter()
+        .filter(|(_, count)| **count > 1)
+        .map(|(position, _)| (position.x, position.y))
+        .sorted_by_key(|(x, y)| (*y, *x))
+        .map(|(x, y)| format!("{x},{y}"))
+        .join(" ");
+    writeln!(
+        output,
+        "Total: {} antinodes",
+        frequencies_per_position.len()
+    )
+    .unwrap();
+    writeln!(output, "Overlapping frequencies at: {overlapping}").unwrap();
+    output += &map.render_antinodes(&frequencies_per_position);
+
+    output
 }
 
-pub fn day8_part2(input: String) -> usize {
-    let mut map = Map::parse_input(input);
+pub fn day8_with_resonance(input: String, resonance: Resonance) -> usize {
+    let mut map = phase("Map::parse_input", || Map::parse_input(input));
 
-    map.place_antinodes(true);
+    phase("Map::place_antinodes", || map.place_antinodes(resonance));
 
     map.antinodes.len()
 }
 
+pub fn day8_part1(input: String) -> usize {
+    day8_with_resonance(input, Resonance::Single)
+}
+
+pub fn day8_part2(input: String) -> usize {
+    day8_with_resonance(input, Resonance::Puzzle)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::day8::{day8_part1, day8_part2};
+    use crate::{
+        day8::{
+            day8_harmonics, day8_part1, day8_part2, day8_with_resonance, gcd, parse_harmonics,
+            parse_ratio, Harmonics, Map, Resonance,
+        },
+        snapshot::assert_snapshot,
+    };
+
+    const EXAMPLE: &str = r#"............
+........0...
+.....0......
+.......0....
+....0.......
+......A.....
+............
+............
+........A...
+.........A..
+............
+............"#;
 
     #[test]
     fn test_part1() {
@@ -189,4 +486,86 @@ mod test {
             )
         )
     }
+
+    #[test]
+    fn test_resonance_modes() {
+        let input = "a.....\n......\n..a...\n......\n......\n......";
+        assert_eq!(1, day8_with_resonance(input.to_string(), Resonance::Single));
+        assert_eq!(3, day8_with_resonance(input.to_string(), Resonance::Puzzle));
+        assert_eq!(
+            6,
+            day8_with_resonance(input.to_string(), Resonance::Collinear)
+        );
+
+        assert_eq!(4, gcd(-8, 12));
+        assert_eq!(3, gcd(0, -3));
+    }
+
+    #[test]
+    fn test_harmonics() {
+        let map = Map::parse_input(EXAMPLE.to_string());
+        let count = |harmonics: &Harmonics, between: bool| {
+            map.harmonic_antinodes(harmonics, between)
+                .into_values()
+                .flatten()
+                .collect::<std::collections::HashSet<_>>()
+                .len()
+        };
+
+        assert_eq!(14, count(&parse_harmonics("1..=1").unwrap(), false));
+        assert_eq!(14, count(&parse_harmonics("1").unwrap(), false));
+        assert_eq!(34, count(&parse_harmonics("0..").unwrap(), false));
+        assert_eq!(14, count(&parse_ratio("1:2").unwrap(), false));
+        assert_eq!(14, count(&parse_ratio("2:4").unwrap(), false));
+
+        let map = Map::parse_input("a..a...".to_string());
+        let antinodes = map.harmonic_antinodes(&parse_ratio("2:1").unwrap(), true);
+        assert_eq!(3, antinodes[&'a'].len());
+        let antinodes = map.harmonic_antinodes(&parse_ratio("1:3").unwrap(), true);
+        assert_eq!(0, antinodes[&'a'].len());
+        let antinodes = map.harmonic_antinodes(&parse_harmonics("1..=2").unwrap(), true);
+        assert_eq!(3, antinodes[&'a'].len());
+
+        let antinodes = map.harmonic_antinodes(&parse_ratio("2147483647:1").unwrap(), true);
+        assert_eq!(0, antinodes[&'a'].len());
+        for ratio in [
+            "2147483646:2147483647",
+            "2147483647:2147483646",
+            "1:2147483647",
+        ] {
+            let map = Map::parse_input(".aa".to_string());
+            let antinodes = map.harmonic_antinodes(&parse_ratio(ratio).unwrap(), true);
+            assert_eq!(0, antinodes[&'a'].len(), "{ratio}");
+        }
+
+        assert!(parse_ratio("0:1").is_err());
+        assert!(parse_ratio("2147483648:1").is_err());
+        assert!(parse_harmonics("x..=2").is_err());
+        assert_eq!(
+            "The range \"3..1\" contains no multiples",
+            parse_harmonics("3..1").unwrap_err()
+        );
+        assert_eq!(
+            Harmonics::Multiples(2..=4),
+            parse_harmonics("2..5").unwrap()
+        );
+        assert_eq!(
+            Harmonics::Multiples(2..=5),
+            parse_harmonics("2..=5").unwrap()
+        );
+        assert!(parse_harmonics("2..2").is_err());
+        assert!(parse_harmonics("0..0").is_err());
+    }
+
+    #[test]
+    fn test_harmonics_report() {
+        assert_snapshot(
+            "day8_harmonics",
+            &day8_harmonics(
+                EXAMPLE.to_string(),
+                &parse_harmonics("1..=2").unwrap(),
+                false,
+            ),
+        );
+    }
 }
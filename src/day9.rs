@@ -1,3 +1,5 @@
+use crate::{profile::phase, util::normalize_input};
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum Data {
     Empty,
@@ -29,7 +31,7 @@ struct Disk {
 fn parse_input(input: String) -> Disk {
     let mut data = Vec::new();
     let mut block_id = 0;
-    for (n, char) in input.trim().chars().enumerate() {
+    for (n, char) in normalize_input(&input).chars().enumerate() {
         if n % 2 == 0 {
             let count = char as usize - '0' as usize;
 
@@ -198,22 +200,56 @@ fn calculate_checksum(disk: Disk) -> usize {
 }
 
 pub fn day9_part1(input: String) -> usize {
-    let mut disk = parse_input(input);
-    defrag(&mut disk, false);
+    let mut disk = phase("parse_input", || parse_input(input));
+    phase("defrag", || defrag(&mut disk, false));
 
-    calculate_checksum(disk)
+    phase("calculate_checksum", || calculate_checksum(disk))
 }
 
 pub fn day9_part2(input: String) -> usize {
-    let mut disk = parse_input(input);
-    defrag(&mut disk, true);
+    let mut disk = phase("parse_input", || parse_input(input));
+    phase("defrag", || defrag(&mut disk, true));
 
-    calculate_checksum(disk)
+    phase("calculate_checksum", || calculate_checksum(disk))
 }
 
 #[cfg(test)]
 mod test {
-    use crate::day9::{day9_part1, day9_part2};
+    use std::collections::HashMap;
+
+    use proptest::prelude::*;
+
+    use crate::day9::{day9_part1, day9_part2, defrag, parse_input, Data, Disk};
+
+    fn file_blocks(disk: &Disk) -> HashMap<usize, usize> {
+        let mut counts = HashMap::new();
+        for block in &disk.data {
+            if let Data::Occupied(id) = block.data {
+                *counts.entry(id).or_insert(0) += block.len;
+            }
+        }
+        counts
+    }
+
+    proptest! {
+        #[test]
+        fn defrag_preserves_file_blocks(
+            layout in prop::collection::vec((1..=9u8, 0..=9u8), 1..20),
+            keep_file_sequence: bool,
+        ) {
+            let input: String = layout
+                .iter()
+                .flat_map(|(file, free)| [file, free])
+                .map(|len| char::from(b'0' + len))
+                .collect();
+            let mut disk = parse_input(input);
+            let before = file_blocks(&disk);
+
+            defrag(&mut disk, keep_file_sequence);
+
+            prop_assert_eq!(before, file_blocks(&disk));
+        }
+    }
 
     #[test]
     fn test_part1() {
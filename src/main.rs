@@ -23,10 +23,14 @@ mod day6;
 mod day7;
 mod day8;
 mod day9;
+mod profile;
+mod repl;
+#[cfg(test)]
+mod snapshot;
 mod util;
 
-use clap::{ArgAction, Parser, Subcommand};
-use day1::{day1_part1, day1_part2};
+use clap::{error::ErrorKind, ArgAction, CommandFactory, Parser, Subcommand};
+use day1::{day1_part1, day1_part2, day1_stats};
 use day10::{day10_part1, day10_part2};
 use day11::{day11_part1, day11_part2};
 use day12::{day12_part1, day12_part2};
@@ -37,22 +41,36 @@ use day16::{day16_part1, day16_part2};
 use day17::{day17_part1, day17_part2};
 use day18::{day18_part1, day18_part2};
 use day19::{day19_part1, day19_part2};
-use day2::{day2_part1, day2_part2};
+use day2::{day2_check, day2_part1, day2_part2, day2_with_tolerance, SafetyPolicy};
 use day20::{day20_part1, day20_part2};
 use day21::{day21_part1, day21_part2};
 use day22::{day22_part1, day22_part2};
 use day23::{day23_part1, day23_part2};
 use day24::{day24_part1, day24_part2};
 use day25::{day25_part1, day25_part2};
-use day3::{day3_part1, day3_part2};
-use day4::{day4_part1, day4_part2};
-use day5::{day5_part1, day5_part2};
-use day6::{day6_part1, day6_part2};
-use day7::{day7_part1, day7_part2};
-use day8::{day8_part1, day8_part2};
+use day3::{
+    day3_part1, day3_part2, day3_trace, day3_with_instructions, parse_instruction_set,
+    InstructionSet, EXTENDED_DIALECT,
+};
+use day4::{day4_part1, day4_part2, day4_search, day4_stencil, Direction, Stencil};
+use day5::{day5_audit, day5_dot, day5_part1, day5_part2, AuditFormat};
+use day6::{day6_loop, day6_part1, day6_part2, day6_patrol, parse_position, Collision, TurnRule};
+use day7::{
+    day7_counts, day7_part1, day7_part2, day7_with_operators, parse_operators, Evaluation,
+    OperatorSet,
+};
+use day8::{
+    day8_harmonics, day8_part1, day8_part2, day8_with_resonance, parse_harmonics, parse_ratio,
+    Harmonics, Resonance,
+};
 use day9::{day9_part1, day9_part2};
-use std::path::PathBuf;
-use util::read_input_or_crash;
+use log::error;
+use profile::CountingAllocator;
+use std::{fmt::Display, path::PathBuf, process::exit};
+use util::{open_input_or_crash, read_input_or_crash};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 /// Simple program to greet a person
 #[derive(Parser)]
@@ -72,42 +90,229 @@ struct Args {
     /// The puzzle input
     #[arg(long = "input", short = 'i')]
     input: PathBuf,
+
+    /// Print a per-phase timing and heap usage breakdown
+    #[arg(long = "profile")]
+    profile: bool,
+}
+
+/// Command line options of the day 2 safety policy
+#[derive(clap::Args)]
+struct PolicyArgs {
+    /// Smallest allowed difference between adjacent levels
+    #[arg(long = "min-step", default_value_t = 1)]
+    min_step: usize,
+
+    /// Largest allowed difference between adjacent levels
+    #[arg(long = "max-step", default_value_t = 3)]
+    max_step: usize,
+
+    /// Allow adjacent levels to be equal
+    #[arg(long = "allow-plateaus")]
+    allow_plateaus: bool,
+
+    /// Allow the levels to change direction once
+    #[arg(long = "allow-direction-change")]
+    allow_direction_change: bool,
+}
+
+impl TryFrom<PolicyArgs> for SafetyPolicy {
+    type Error = String;
+
+    fn try_from(args: PolicyArgs) -> Result<Self, Self::Error> {
+        Ok(SafetyPolicy::new(args.min_step, args.max_step)?
+            .allow_plateaus(args.allow_plateaus)
+            .allow_direction_change(args.allow_direction_change))
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    #[command(flatten)]
+    Streaming(Streaming),
+    #[command(flatten)]
+    Puzzle(Puzzle),
+    /// Interactively explore the parsed puzzle model of a day
+    Repl {
+        /// The day to load the input for
+        day: u8,
+    },
+}
+
+/// Commands solving a puzzle directly from the input file without loading it into memory
+#[derive(Subcommand)]
+enum Streaming {
     /// Day 1: Historian Hysteria: part 1
     Day1_1,
     /// Day 1: Historian Hysteria: part 2
     Day1_2,
+}
+
+/// Commands solving a puzzle and printing the result
+#[derive(Subcommand)]
+enum Puzzle {
+    /// Day 1: Historian Hysteria: location list statistics
+    Day1Stats {
+        /// Number of entries to list in the rankings
+        #[arg(long = "top", default_value_t = 5)]
+        top: usize,
+    },
     /// Day 2: Red-Nosed Reports: part 1
     Day2_1,
     /// Day 2: Red-Nosed Reports: part 2
     Day2_2,
+    /// Day 2: Red-Nosed Reports: tolerate up to k bad levels
+    Day2Tolerance {
+        /// Maximum number of levels to remove
+        #[arg(long = "tolerance", short = 'k')]
+        tolerance: usize,
+
+        #[command(flatten)]
+        policy: PolicyArgs,
+    },
+    /// Day 2: Red-Nosed Reports: explain which rule each unsafe report breaks
+    Day2Check {
+        #[command(flatten)]
+        policy: PolicyArgs,
+    },
     /// Day 3: Mull It Over: part 1
     Day3_1,
     /// Day 3: Mull It Over: part 2
     Day3_2,
+    /// Day 3: Mull It Over: run a configurable dialect, by default extended with add, neg and nested calls
+    Day3Extended {
+        /// Comma separated instructions name[=operation][:min-max], operations are mul, add,
+        /// neg, enable and disable, digits default to 1-3
+        #[arg(long = "instructions", value_parser = parse_instruction_set, default_value = EXTENDED_DIALECT)]
+        instructions: InstructionSet,
+
+        /// How deep calls may be nested as arguments, 0 disallows nesting
+        #[arg(long = "max-depth", default_value_t = 16)]
+        max_depth: usize,
+
+        /// List the executed instructions with their byte offset in the input
+        #[arg(long = "trace")]
+        trace: bool,
+    },
     /// Day 4: Ceres Search: part 1
     Day4_1,
     /// Day 4: Ceres Search: part 2
     Day4_2,
+    /// Day 4: Ceres Search: find any words in the grid
+    Day4Search {
+        /// The words to search for
+        #[arg(required = true)]
+        words: Vec<String>,
+
+        /// Only search in these directions (all by default)
+        #[arg(long = "direction", value_enum)]
+        directions: Vec<Direction>,
+
+        /// Also find words written backwards
+        #[arg(long = "reversed")]
+        reversed: bool,
+
+        /// Continue words on the opposite edge of the grid
+        #[arg(long = "wrap-around")]
+        wrap_around: bool,
+    },
+    /// Day 4: Ceres Search: find a 2D pattern such as "M.S/.A./M.S" in the grid
+    Day4Stencil {
+        /// Rows separated by '/', '.' matches any letter
+        stencil: Stencil,
+
+        /// Also match all rotations and reflections
+        #[arg(long = "transforms")]
+        transforms: bool,
+    },
     /// Day 5: Print Queue: part 1
     Day5_1,
     /// Day 5: Print Queue: part 2
     Day5_2,
+    /// Day 5: Print Queue: report the violated rules of every update
+    Day5Audit {
+        /// Output format of the report
+        #[arg(long = "format", value_enum, default_value_t = AuditFormat::Text)]
+        format: AuditFormat,
+    },
+    /// Day 5: Print Queue: export the ordering rules as a Graphviz digraph
+    Day5Dot {
+        /// Only include the pages of this update (counting from 1) and highlight its violations
+        #[arg(long = "update")]
+        update: Option<usize>,
+
+        /// Leave out rules implied by other rules
+        #[arg(long = "transitive-reduction")]
+        transitive_reduction: bool,
+    },
     /// Day 6: Guard Gallivant: part 1
     Day6_1,
     /// Day 6: Guard Gallivant: part 2
     Day6_2,
+    /// Day 6: Guard Gallivant: simulate all guards on the map
+    Day6Patrol {
+        /// Where guards turn when they hit an obstacle
+        #[arg(long = "turn", value_enum, default_value_t = TurnRule::Right)]
+        turn: TurnRule,
+
+        /// What happens when guards run into each other
+        #[arg(long = "collision", value_enum, default_value_t = Collision::Ignore)]
+        collision: Collision,
+    },
+    /// Day 6: Guard Gallivant: describe the loop caused by an additional obstacle
+    Day6Loop {
+        /// Position x,y of the additional obstacle
+        #[arg(long = "obstacle", value_parser = parse_position)]
+        obstacle: Option<(isize, isize)>,
+    },
     /// Day 7: Bridge Repair: part 1
     Day7_1,
     /// Day 7: Bridge Repair: part 2
     Day7_2,
+    /// Day 7: Bridge Repair: solve with a custom set of operators
+    Day7Ops {
+        /// Comma separated operators out of +, *, ||, -, / (exact), ^ and %
+        #[arg(long = "ops", value_parser = parse_operators)]
+        ops: OperatorSet,
+    },
+    /// Day 7: Bridge Repair: count the operator assignments solving each calibration
+    Day7Count {
+        /// Comma separated operators out of +, *, ||, -, / (exact), ^ and %
+        #[arg(long = "ops", value_parser = parse_operators, default_value = "+,*,||")]
+        ops: OperatorSet,
+
+        /// How the operators are evaluated
+        #[arg(long = "evaluation", value_enum, default_value_t = Evaluation::LeftToRight)]
+        evaluation: Evaluation,
+
+        /// Also list every solving equation
+        #[arg(long = "list")]
+        list: bool,
+    },
     ///Day 8: Resonant Collinearity: part 1
     Day8_1,
     ///Day 8: Resonant Collinearity: part 2
     Day8_2,
+    ///Day 8: Resonant Collinearity: count antinodes with a chosen resonance model
+    Day8Resonance {
+        /// Where antinodes appear on the line through two antennas
+        #[arg(long = "mode", value_enum)]
+        mode: Resonance,
+    },
+    ///Day 8: Resonant Collinearity: per frequency antinodes for custom harmonics
+    Day8Harmonics {
+        /// Multiples of the antenna distance like 1..=3, 1..4 or 0..
+        #[arg(long = "harmonics", value_parser = parse_harmonics, conflicts_with = "ratio")]
+        harmonics: Option<Harmonics>,
+
+        /// Ratio of the distances to both antennas like 1:3
+        #[arg(long = "ratio", value_parser = parse_ratio)]
+        ratio: Option<Harmonics>,
+
+        /// Also place antinodes between the antennas
+        #[arg(long = "between")]
+        between: bool,
+    },
     ///Day 9: Disk Fragmenter: part 1
     Day9_1,
     ///Day 9: Disk Fragmenter: part 2
@@ -181,6 +386,23 @@ enum Commands {
 fn main() {
     let args = Args::parse();
 
+    // Equal distances to both antennas only occur between them
+    if let Commands::Puzzle(Puzzle::Day8Harmonics {
+        ratio: Some(Harmonics::Ratio(p, q)),
+        between: false,
+        ..
+    }) = &args.command
+    {
+        if p == q {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("the ratio {p}:{q} has no antinodes without '--between'"),
+                )
+                .exit();
+        }
+    }
+
     stderrlog::new()
         .module(module_path!())
         .quiet(args.quiet)
@@ -188,60 +410,149 @@ fn main() {
         .init()
         .unwrap();
 
-    let input = read_input_or_crash(args.input);
+    if args.profile {
+        profile::enable();
+    }
 
     let result: String = match args.command {
-        Commands::Day1_1 => day1_part1(input).to_string(),
-        Commands::Day1_2 => day1_part2(input).to_string(),
-        Commands::Day2_1 => day2_part1(input).to_string(),
-        Commands::Day2_2 => day2_part2(input).to_string(),
-        Commands::Day3_1 => day3_part1(input).to_string(),
-        Commands::Day3_2 => day3_part2(input).to_string(),
-        Commands::Day4_1 => day4_part1(input).to_string(),
-        Commands::Day4_2 => day4_part2(input).to_string(),
-        Commands::Day5_1 => day5_part1(input).to_string(),
-        Commands::Day5_2 => day5_part2(input).to_string(),
-        Commands::Day6_1 => day6_part1(input).to_string(),
-        Commands::Day6_2 => day6_part2(input).to_string(),
-        Commands::Day7_1 => day7_part1(input).to_string(),
-        Commands::Day7_2 => day7_part2(input).to_string(),
-        Commands::Day8_1 => day8_part1(input).to_string(),
-        Commands::Day8_2 => day8_part2(input).to_string(),
-        Commands::Day9_1 => day9_part1(input).to_string(),
-        Commands::Day9_2 => day9_part2(input).to_string(),
-        Commands::Day10_1 => day10_part1(input).to_string(),
-        Commands::Day10_2 => day10_part2(input).to_string(),
-        Commands::Day11_1 => day11_part1(input).to_string(),
-        Commands::Day11_2 => day11_part2(input).to_string(),
-        Commands::Day12_1 => day12_part1(input).to_string(),
-        Commands::Day12_2 => day12_part2(input).to_string(),
-        Commands::Day13_1 => day13_part1(input).to_string(),
-        Commands::Day13_2 => day13_part2(input).to_string(),
-        Commands::Day14_1 => day14_part1(input).to_string(),
-        Commands::Day14_2 => day14_part2(input).to_string(),
-        Commands::Day15_1 => day15_part1(input).to_string(),
-        Commands::Day15_2 => day15_part2(input).to_string(),
-        Commands::Day16_1 => day16_part1(input).to_string(),
-        Commands::Day16_2 => day16_part2(input).to_string(),
-        Commands::Day17_1 => day17_part1(input),
-        Commands::Day17_2 => day17_part2(input).to_string(),
-        Commands::Day18_1 => day18_part1(input).to_string(),
-        Commands::Day18_2 => day18_part2(input),
-        Commands::Day19_1 => day19_part1(input).to_string(),
-        Commands::Day19_2 => day19_part2(input).to_string(),
-        Commands::Day20_1 => day20_part1(input).to_string(),
-        Commands::Day20_2 => day20_part2(input).to_string(),
-        Commands::Day21_1 => day21_part1(input).to_string(),
-        Commands::Day21_2 => day21_part2(input).to_string(),
-        Commands::Day22_1 => day22_part1(input).to_string(),
-        Commands::Day22_2 => day22_part2(input).to_string(),
-        Commands::Day23_1 => day23_part1(input).to_string(),
-        Commands::Day23_2 => day23_part2(input).to_string(),
-        Commands::Day24_1 => day24_part1(input).to_string(),
-        Commands::Day24_2 => day24_part2(input),
-        Commands::Day25_1 => day25_part1(input).to_string(),
-        Commands::Day25_2 => day25_part2(input).to_string(),
+        Commands::Streaming(command) => {
+            let reader = open_input_or_crash(args.input);
+
+            let result = profile::phase("solve", || match command {
+                Streaming::Day1_1 => day1_part1(reader),
+                Streaming::Day1_2 => day1_part2(reader),
+            });
+            unwrap_or_crash(result).to_string()
+        }
+        Commands::Puzzle(command) => {
+            let input = profile::phase("read input", || read_input_or_crash(args.input));
+
+            profile::phase("solve", || match command {
+                Puzzle::Day1Stats { top } => unwrap_or_crash(day1_stats(input, top)),
+                Puzzle::Day2_1 => day2_part1(input).to_string(),
+                Puzzle::Day2_2 => day2_part2(input).to_string(),
+                Puzzle::Day2Tolerance { tolerance, policy } => {
+                    let policy = unwrap_or_crash(policy.try_into());
+                    day2_with_tolerance(input, tolerance, policy).to_string()
+                }
+                Puzzle::Day2Check { policy } => {
+                    day2_check(input, unwrap_or_crash(policy.try_into()))
+                }
+                Puzzle::Day3_1 => unwrap_or_crash(day3_part1(input)).to_string(),
+                Puzzle::Day3_2 => unwrap_or_crash(day3_part2(input)).to_string(),
+                Puzzle::Day3Extended {
+                    instructions,
+                    max_depth,
+                    trace,
+                } => {
+                    let instructions = instructions.with_max_depth(max_depth);
+                    if trace {
+                        unwrap_or_crash(day3_trace(input, &instructions))
+                    } else {
+                        unwrap_or_crash(day3_with_instructions(input, &instructions)).to_string()
+                    }
+                }
+                Puzzle::Day4_1 => day4_part1(input).to_string(),
+                Puzzle::Day4_2 => day4_part2(input).to_string(),
+                Puzzle::Day4Search {
+                    words,
+                    directions,
+                    reversed,
+                    wrap_around,
+                } => day4_search(input, &words, &directions, reversed, wrap_around),
+                Puzzle::Day4Stencil {
+                    stencil,
+                    transforms,
+                } => day4_stencil(input, &stencil, transforms),
+                Puzzle::Day5_1 => day5_part1(input).to_string(),
+                Puzzle::Day5_2 => day5_part2(input).to_string(),
+                Puzzle::Day5Audit { format } => day5_audit(input, format),
+                Puzzle::Day5Dot {
+                    update,
+                    transitive_reduction,
+                } => unwrap_or_crash(day5_dot(input, update, transitive_reduction)),
+                Puzzle::Day6_1 => day6_part1(input).to_string(),
+                Puzzle::Day6_2 => day6_part2(input).to_string(),
+                Puzzle::Day6Patrol { turn, collision } => day6_patrol(input, turn, collision),
+                Puzzle::Day6Loop { obstacle } => unwrap_or_crash(day6_loop(input, obstacle)),
+                Puzzle::Day7_1 => unwrap_or_crash(day7_part1(input)).to_string(),
+                Puzzle::Day7_2 => unwrap_or_crash(day7_part2(input)).to_string(),
+                Puzzle::Day7Ops { ops } => {
+                    unwrap_or_crash(day7_with_operators(input, &ops)).to_string()
+                }
+                Puzzle::Day7Count {
+                    ops,
+                    evaluation,
+                    list,
+                } => day7_counts(input, &ops, evaluation, list),
+                Puzzle::Day8_1 => day8_part1(input).to_string(),
+                Puzzle::Day8_2 => day8_part2(input).to_string(),
+                Puzzle::Day8Resonance { mode } => day8_with_resonance(input, mode).to_string(),
+                Puzzle::Day8Harmonics {
+                    harmonics,
+                    ratio,
+                    between,
+                } => {
+                    let harmonics = harmonics.or(ratio).unwrap_or(Harmonics::Multiples(1..=1));
+                    day8_harmonics(input, &harmonics, between)
+                }
+                Puzzle::Day9_1 => day9_part1(input).to_string(),
+                Puzzle::Day9_2 => day9_part2(input).to_string(),
+                Puzzle::Day10_1 => day10_part1(input).to_string(),
+                Puzzle::Day10_2 => day10_part2(input).to_string(),
+                Puzzle::Day11_1 => day11_part1(input).to_string(),
+                Puzzle::Day11_2 => day11_part2(input).to_string(),
+                Puzzle::Day12_1 => day12_part1(input).to_string(),
+                Puzzle::Day12_2 => day12_part2(input).to_string(),
+                Puzzle::Day13_1 => day13_part1(input).to_string(),
+                Puzzle::Day13_2 => day13_part2(input).to_string(),
+                Puzzle::Day14_1 => day14_part1(input).to_string(),
+                Puzzle::Day14_2 => day14_part2(input).to_string(),
+                Puzzle::Day15_1 => day15_part1(input).to_string(),
+                Puzzle::Day15_2 => day15_part2(input).to_string(),
+                Puzzle::Day16_1 => day16_part1(input).to_string(),
+                Puzzle::Day16_2 => day16_part2(input).to_string(),
+                Puzzle::Day17_1 => day17_part1(input),
+                Puzzle::Day17_2 => day17_part2(input).to_string(),
+                Puzzle::Day18_1 => day18_part1(input).to_string(),
+                Puzzle::Day18_2 => day18_part2(input),
+                Puzzle::Day19_1 => day19_part1(input).to_string(),
+                Puzzle::Day19_2 => day19_part2(input).to_string(),
+                Puzzle::Day20_1 => day20_part1(input).to_string(),
+                Puzzle::Day20_2 => day20_part2(input).to_string(),
+                Puzzle::Day21_1 => day21_part1(input).to_string(),
+                Puzzle::Day21_2 => day21_part2(input).to_string(),
+                Puzzle::Day22_1 => day22_part1(input).to_string(),
+                Puzzle::Day22_2 => day22_part2(input).to_string(),
+                Puzzle::Day23_1 => day23_part1(input).to_string(),
+                Puzzle::Day23_2 => day23_part2(input).to_string(),
+                Puzzle::Day24_1 => day24_part1(input).to_string(),
+                Puzzle::Day24_2 => day24_part2(input),
+                Puzzle::Day25_1 => day25_part1(input).to_string(),
+                Puzzle::Day25_2 => day25_part2(input).to_string(),
+            })
+        }
+        Commands::Repl { day } => {
+            let input = profile::phase("read input", || read_input_or_crash(args.input));
+            repl::run(day, input);
+            return;
+        }
     };
 
-    println!("Result: {result}")
+    println!("Result: {result}");
+
+    if args.profile {
+        print!("{}", profile::report());
+    }
+}
+
+/// Unwrap the result of a solver, logging the error and exiting otherwise.
+fn unwrap_or_crash<T, E: Display>(result: Result<T, E>) -> T {
+    match result {
+        Ok(result) => result,
+        Err(e) => {
+            error!("{e}");
+            exit(1)
+        }
+    }
 }
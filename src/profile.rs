@@ -0,0 +1,278 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of peak heap measurements that can be running at the same time.
+const WATERMARKS: usize = 64;
+/// Watermarks owned by a running measurement
+static CLAIMED: AtomicU64 = AtomicU64::new(0);
+/// Watermarks that are updated on every allocation
+static ACTIVE: AtomicU64 = AtomicU64::new(0);
+static WATERMARK_PEAKS: [AtomicUsize; WATERMARKS] = [const { AtomicUsize::new(0) }; WATERMARKS];
+
+/// Global allocator wrapping the system allocator that keeps track of the
+/// currently allocated and the peak number of heap bytes.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                record_allocation(new_size - layout.size());
+            } else {
+                ALLOCATED.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+            }
+        }
+        new_ptr
+    }
+}
+
+fn record_allocation(size: usize) {
+    let current = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+
+    let mut active = ACTIVE.load(Ordering::Relaxed);
+    while active != 0 {
+        WATERMARK_PEAKS[active.trailing_zeros() as usize].fetch_max(current, Ordering::Relaxed);
+        active &= active - 1;
+    }
+}
+
+/// Start measuring the peak heap usage from now on. Every measurement has its own
+/// watermark, so measurements running at the same time on other threads don't reset
+/// each other. Returns `None` when all watermarks are in use.
+fn start_watermark() -> Option<usize> {
+    let claimed = CLAIMED
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |claimed| {
+            (claimed != u64::MAX).then(|| claimed | (claimed + 1))
+        })
+        .ok()?;
+    let watermark = (!claimed).trailing_zeros() as usize;
+
+    WATERMARK_PEAKS[watermark].store(ALLOCATED.load(Ordering::Relaxed), Ordering::Relaxed);
+    ACTIVE.fetch_or(1 << watermark, Ordering::Relaxed);
+
+    Some(watermark)
+}
+
+/// Stop a measurement started by `start_watermark` and return the peak it saw.
+fn stop_watermark(watermark: usize) -> usize {
+    ACTIVE.fetch_and(!(1 << watermark), Ordering::Relaxed);
+    let peak = WATERMARK_PEAKS[watermark].load(Ordering::Relaxed);
+    CLAIMED.fetch_and(!(1 << watermark), Ordering::Relaxed);
+
+    peak
+}
+
+#[derive(Debug, Clone)]
+pub struct Phase {
+    pub name: &'static str,
+    /// Nesting depth on the thread the phase ran on
+    pub depth: usize,
+    pub thread: ThreadId,
+    pub duration: Duration,
+    /// Peak heap usage of the whole process while the phase ran
+    pub peak_heap: usize,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PHASES: Mutex<Vec<Phase>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Start recording phases on all threads.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Run `f` as a named phase. When profiling is enabled, the duration and the
+/// peak heap usage while `f` runs are recorded. Phases may be nested and may run
+/// on any thread.
+pub fn phase<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+
+    let index = {
+        let mut phases = PHASES.lock().unwrap();
+        phases.push(Phase {
+            name,
+            depth: DEPTH.get(),
+            thread: thread::current().id(),
+            duration: Duration::ZERO,
+            peak_heap: 0,
+        });
+        phases.len() - 1
+    };
+    let watermark = start_watermark();
+    DEPTH.set(DEPTH.get() + 1);
+    let start = Instant::now();
+
+    let result = f();
+
+    let duration = start.elapsed();
+    DEPTH.set(DEPTH.get() - 1);
+    let peak_heap = watermark.map_or(0, stop_watermark);
+    let mut phases = PHASES.lock().unwrap();
+    phases[index].duration = duration;
+    phases[index].peak_heap = peak_heap;
+
+    result
+}
+
+/// Take all phases recorded so far.
+pub fn take_phases() -> Vec<Phase> {
+    std::mem::take(&mut PHASES.lock().unwrap())
+}
+
+pub fn peak_heap() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+/// Render the recorded phases as an indented table.
+pub fn report() -> String {
+    let main_thread = thread::current().id();
+    let phases: Vec<(String, Phase)> = take_phases()
+        .into_iter()
+        .map(|phase| {
+            let mut name = format!("{}{}", "  ".repeat(phase.depth), phase.name);
+            if phase.thread != main_thread {
+                write!(name, " on {:?}", phase.thread).unwrap();
+            }
+            (name, phase)
+        })
+        .collect();
+    let name_width = phases.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+    let mut output = String::new();
+    writeln!(output, "Profile:").unwrap();
+    for (name, phase) in phases {
+        writeln!(
+            output,
+            "  {name:<name_width$}  {:>12.3?}  peak heap {:>10}",
+            phase.duration,
+            format_bytes(phase.peak_heap)
+        )
+        .unwrap();
+    }
+    writeln!(output, "Peak heap usage: {}", format_bytes(peak_heap())).unwrap();
+
+    output
+}
+
+fn format_bytes(bytes: usize) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", units[unit])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::{enable, format_bytes, phase, Phase, PHASES};
+
+    /// Phases recorded on the given thread. Other tests may record phases at the same time,
+    /// so they are left in place.
+    fn phases_of(thread: thread::ThreadId) -> Vec<Phase> {
+        PHASES
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|phase| phase.thread == thread)
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_nested_phases() {
+        enable();
+        let result = phase("outer", || {
+            let inner = phase("inner", || vec![1u8; 4096]);
+            inner.len()
+        });
+
+        let phases = phases_of(thread::current().id());
+        assert_eq!(4096, result);
+        assert_eq!(
+            vec![("outer", 0), ("inner", 1)],
+            phases
+                .iter()
+                .map(|phase| (phase.name, phase.depth))
+                .collect::<Vec<_>>()
+        );
+        assert!(phases[1].peak_heap >= 4096);
+        assert!(phases[0].duration >= phases[1].duration);
+    }
+
+    #[test]
+    fn test_phases_on_worker_threads() {
+        enable();
+        let worker = thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    phase("worker", || vec![1u8; 8192]).len();
+                    thread::current().id()
+                })
+                .join()
+                .unwrap()
+        });
+
+        let phases = phases_of(worker);
+        assert_eq!(1, phases.len());
+        assert_eq!(("worker", 0), (phases[0].name, phases[0].depth));
+        assert!(phases[0].peak_heap >= 8192);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!("512 B", format_bytes(512));
+        assert_eq!("1.5 KiB", format_bytes(1536));
+        assert_eq!("2.0 MiB", format_bytes(2 * 1024 * 1024));
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    fmt::Display,
+    io::{self, BufRead, Write},
+    process::exit,
+    str::FromStr,
+};
+
+use log::error;
+
+use crate::{day11::day11_repl, day14::day14_repl, day17::day17_repl, day24::day24_repl};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplError {
+    UnknownCommand(String),
+    MissingArgument(&'static str),
+    InvalidArgument(String),
+    /// The command ran but could not produce a result
+    Failed(String),
+}
+
+impl Display for ReplError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplError::UnknownCommand(command) => {
+                write!(f, "Unknown command {command}, try help")
+            }
+            ReplError::MissingArgument(name) => write!(f, "Missing argument <{name}>"),
+            ReplError::InvalidArgument(value) => write!(f, "Invalid argument {value}"),
+            ReplError::Failed(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// A parsed puzzle model that can be inspected and manipulated interactively.
+pub trait Explore {
+    /// Supported commands as pairs of usage and description.
+    fn commands(&self) -> &'static [(&'static str, &'static str)];
+
+    /// Run a single command and return the text to show to the user.
+    fn execute(&mut self, command: &str, args: &[&str]) -> Result<String, ReplError>;
+}
+
+pub fn run(day: u8, input: String) {
+    let model = match day {
+        11 => day11_repl(input),
+        14 => day14_repl(input),
+        17 => day17_repl(input),
+        24 => day24_repl(input),
+        _ => {
+            error!("There is no REPL for day {day}");
+            exit(1)
+        }
+    };
+
+    run_session(model, io::stdin().lock(), io::stdout()).unwrap();
+}
+
+fn run_session(
+    mut model: Box<dyn Explore>,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    write!(output, "> ")?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.first() {
+            None => {}
+            Some(&"quit") | Some(&"exit") => break,
+            Some(&"help") => {
+                for (usage, description) in model.commands() {
+                    writeln!(output, "{usage:<20} {description}")?;
+                }
+                writeln!(output, "{:<20} Leave the REPL", "quit")?;
+            }
+            Some(command) => match model.execute(command, &words[1..]) {
+                Ok(result) if result.is_empty() => {}
+                Ok(result) => writeln!(output, "{}", result.trim_end())?,
+                Err(e) => writeln!(output, "Error: {e}")?,
+            },
+        }
+
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Parse the required argument at `index`.
+pub fn parse_arg<T: FromStr>(
+    args: &[&str],
+    index: usize,
+    name: &'static str,
+) -> Result<T, ReplError> {
+    let value = args.get(index).ok_or(ReplError::MissingArgument(name))?;
+    value
+        .parse()
+        .map_err(|_| ReplError::InvalidArgument(value.to_string()))
+}
+
+/// Parse the argument at `index`, falling back to `default` if it is missing.
+pub fn parse_optional_arg<T: FromStr>(
+    args: &[&str],
+    index: usize,
+    default: T,
+) -> Result<T, ReplError> {
+    match args.get(index) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| ReplError::InvalidArgument(value.to_string())),
+        None => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::day11::day11_repl;
+
+    use super::{parse_arg, parse_optional_arg, run_session, ReplError};
+
+    #[test]
+    fn test_session() {
+        let mut output = Vec::new();
+        run_session(
+            day11_repl("125 17".to_string()),
+            "count\nblink 25\n\nfoo\ncount\nquit\ncount\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            "> 2\n> > > Error: Unknown command foo, try help\n> 55312\n> ",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(Ok(3), parse_arg::<usize>(&["3"], 0, "n"));
+        assert_eq!(
+            Err(ReplError::MissingArgument("n")),
+            parse_arg::<usize>(&[], 0, "n")
+        );
+        assert_eq!(
+            Err(ReplError::InvalidArgument("x".to_string())),
+            parse_arg::<usize>(&["x"], 0, "n")
+        );
+        assert_eq!(Ok(1), parse_optional_arg::<usize>(&[], 0, 1));
+    }
+}
@@ -0,0 +1,92 @@
+use std::{env, fs, path::PathBuf};
+
+/// Compare `actual` against the fixture `snapshots/<name>.txt`.
+///
+/// Fixtures are only written when `UPDATE_SNAPSHOTS=1` is set, both to create
+/// new fixtures and to overwrite existing ones after an intended change. A
+/// missing fixture fails the test.
+pub fn assert_snapshot(name: &str, actual: &str) {
+    let update = env::var("UPDATE_SNAPSHOTS").is_ok_and(|update| update == "1");
+    check_snapshot(name, actual, update);
+}
+
+fn check_snapshot(name: &str, actual: &str, update: bool) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("snapshots")
+        .join(format!("{name}.txt"));
+
+    if update {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "Could not read snapshot {name} from {}: {e}\nRerun with UPDATE_SNAPSHOTS=1 to create it.",
+            path.display()
+        )
+    });
+    if expected != actual {
+        panic!(
+            "Snapshot {name} does not match {} (- expected, + actual):\n{}\nRerun with UPDATE_SNAPSHOTS=1 to accept the new output.",
+            path.display(),
+            diff_lines(&expected, actual)
+        );
+    }
+}
+
+/// Line based diff using the longest common subsequence of both inputs.
+fn diff_lines(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut output = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            output.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1])
+        {
+            output.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            output.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+
+    output.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_snapshot, diff_lines};
+
+    #[test]
+    #[should_panic(expected = "Could not read snapshot does_not_exist")]
+    fn test_missing_snapshot() {
+        check_snapshot("does_not_exist", "", false);
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(
+            "  #..\n- .O.\n+ .@.\n  ..#\n+ ...",
+            diff_lines("#..\n.O.\n..#", "#..\n.@.\n..#\n...")
+        );
+    }
+}
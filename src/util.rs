@@ -1,5 +1,12 @@
 use log::error;
-use std::{fs, path::PathBuf, process::exit};
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::BufReader,
+    path::PathBuf,
+    process::exit,
+    str::FromStr,
+};
 
 pub fn read_input_or_crash(path: PathBuf) -> String {
     match fs::read_to_string(path.clone()) {
@@ -11,3 +18,135 @@ pub fn read_input_or_crash(path: PathBuf) -> String {
         }
     }
 }
+
+pub fn open_input_or_crash(path: PathBuf) -> BufReader<File> {
+    match File::open(&path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => {
+            let path_display = path.display();
+            error!("Could not open {path_display}: {e}");
+            exit(1)
+        }
+    }
+}
+
+/// Normalize raw puzzle input so parsers only have to deal with `\n`
+/// separated lines: strips a byte order mark, converts CRLF line endings, strips
+/// trailing whitespace from every line and drops blank lines at the start and the end.
+/// Lines holding only whitespace end up empty, so they separate sections.
+pub fn normalize_input(input: &str) -> String {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+
+    input
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_string()
+}
+
+/// Split normalized input into its blank line separated sections.
+pub fn sections(input: &str) -> impl Iterator<Item = &str> {
+    input
+        .split("\n\n")
+        .map(|section| section.trim_matches('\n'))
+        .filter(|section| !section.is_empty())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SectionError {
+    Count { expected: usize, found: usize },
+    Invalid { section: usize, message: String },
+}
+
+impl Display for SectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SectionError::Count { expected, found } => {
+                write!(
+                    f,
+                    "Expected {expected} sections in the input, found {found}"
+                )
+            }
+            SectionError::Invalid { section, message } => {
+                write!(f, "Invalid section {section}: {message}")
+            }
+        }
+    }
+}
+
+fn parse_section<T>(section: &str, index: usize) -> Result<T, SectionError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    section.parse().map_err(|e: T::Err| SectionError::Invalid {
+        section: index + 1,
+        message: e.to_string(),
+    })
+}
+
+/// Parse normalized input made of two blank line separated sections, each with its own type.
+pub fn parse_sections<A, B>(input: &str) -> Result<(A, B), SectionError>
+where
+    A: FromStr,
+    A::Err: Display,
+    B: FromStr,
+    B::Err: Display,
+{
+    let sections: Vec<&str> = sections(input).collect();
+    let [a, b] = sections[..] else {
+        return Err(SectionError::Count {
+            expected: 2,
+            found: sections.len(),
+        });
+    };
+
+    Ok((parse_section(a, 0)?, parse_section(b, 1)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{normalize_input, parse_sections, sections, SectionError};
+
+    #[test]
+    fn test_normalize_input() {
+        assert_eq!(
+            "1 2\n 3 4\n\n\n5",
+            normalize_input("\u{feff}\r\n1 2  \r\n 3 4\r\n\r\n  \r\n5\r\n\r\n")
+        );
+        assert_eq!("", normalize_input("\n\n"));
+        assert_eq!("#.\n.#", normalize_input("\n \t\n#. \n.#\n   \n"));
+    }
+
+    #[test]
+    fn test_sections() {
+        let input = normalize_input("a\nb\n\n\nc\n\nd\n");
+        assert_eq!(vec!["a\nb", "c", "d"], sections(&input).collect::<Vec<_>>());
+
+        let input = normalize_input("a\n  \nb \n\t\r\nc");
+        assert_eq!(vec!["a", "b", "c"], sections(&input).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_parse_sections() {
+        assert_eq!(
+            Ok((12, "a b".to_string())),
+            parse_sections::<usize, String>("12\n\na b")
+        );
+        assert_eq!(
+            Err(SectionError::Count {
+                expected: 2,
+                found: 3
+            }),
+            parse_sections::<String, String>("a\n\nb\n\nc")
+        );
+        assert_eq!(
+            "Invalid section 2: invalid digit found in string",
+            parse_sections::<usize, usize>("1\n\nx")
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}